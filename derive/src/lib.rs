@@ -0,0 +1,324 @@
+//! `#[derive(Schema)]` for lmtreedb. Generates the same things as `def_schema!`, but also supports generics.
+//!
+//! Reexported as `lmtreedb::schema::Schema`, see its documentation for usage.
+
+extern crate proc_macro;
+
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::quote;
+use syn::parse::{Parse, ParseStream};
+use syn::punctuated::Punctuated;
+use syn::spanned::Spanned;
+use syn::{parenthesized, parse_macro_input, DeriveInput, Ident, LitInt, Token, Type};
+
+/// Single argument of `#[schema(...)]` attribute
+enum SchemaArg {
+    Version(LitInt),
+    Prev(Type),
+    Next(Type),
+    Last,
+    Serde,
+    Shortcut(Vec<Type>),
+    Lossless,
+    Lossy,
+}
+
+impl Parse for SchemaArg {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let name: Ident = input.parse()?;
+        let res = match name.to_string().as_str() {
+            "version" => {
+                input.parse::<Token![=]>()?;
+                SchemaArg::Version(input.parse()?)
+            }
+            "prev" => {
+                input.parse::<Token![=]>()?;
+                SchemaArg::Prev(input.parse()?)
+            }
+            "next" => {
+                input.parse::<Token![=]>()?;
+                SchemaArg::Next(input.parse()?)
+            }
+            "last" => SchemaArg::Last,
+            "serde" => SchemaArg::Serde,
+            "lossless" => SchemaArg::Lossless,
+            "lossy" => SchemaArg::Lossy,
+            "shortcut" => {
+                let content;
+                parenthesized!(content in input);
+                let types = Punctuated::<Type, Token![,]>::parse_terminated(&content)?;
+                SchemaArg::Shortcut(types.into_iter().collect())
+            }
+            other => {
+                return Err(syn::Error::new(
+                    name.span(),
+                    format!("Unknown schema argument: `{}`", other),
+                ))
+            }
+        };
+        Ok(res)
+    }
+}
+
+/// All arguments from all `#[schema(...)]` attributes of the type
+#[derive(Default)]
+struct SchemaArgs {
+    version: Option<LitInt>,
+    prev: Option<Type>,
+    next: Option<Type>,
+    last: bool,
+    serde: bool,
+    shortcuts: Vec<Type>,
+    lossiness: Option<Ident>,
+}
+
+impl SchemaArgs {
+    fn from_input(input: &DeriveInput) -> syn::Result<Self> {
+        let mut res = SchemaArgs::default();
+        for attr in &input.attrs {
+            if !attr.path.is_ident("schema") {
+                continue;
+            }
+            let args = attr.parse_args_with(Punctuated::<SchemaArg, Token![,]>::parse_terminated)?;
+            for arg in args {
+                match arg {
+                    SchemaArg::Version(v) => res.version = Some(v),
+                    SchemaArg::Prev(t) => res.prev = Some(t),
+                    SchemaArg::Next(t) => res.next = Some(t),
+                    SchemaArg::Last => res.last = true,
+                    SchemaArg::Serde => res.serde = true,
+                    SchemaArg::Shortcut(mut t) => res.shortcuts.append(&mut t),
+                    SchemaArg::Lossless => {
+                        res.lossiness = Some(Ident::new("Lossless", attr.path.span()))
+                    }
+                    SchemaArg::Lossy => res.lossiness = Some(Ident::new("Lossy", attr.path.span())),
+                }
+            }
+        }
+        Ok(res)
+    }
+}
+
+/// Implements `Schema` and all required parts of it.
+///
+/// ```ignore
+/// #[derive(Debug, Serialize, Deserialize, Schema)]
+/// #[schema(version = 3, prev = FooV2, serde)]
+/// #[schema(last)]
+/// struct FooV3 { ... }
+/// ```
+///
+/// Arguments:
+/// - `version = N`: required, the same as in `def_schema!(T = N)`. Version 1 is always the first one.
+/// - `last`: it is the last version, like `def_schema!(T = [N])`
+/// - `serde`: implements `SchemaSerdeMarker`
+/// - `prev = T`: implements `SchemaUpgrade` using `From<T> for Self`
+/// - `next = T`: implements `SchemaDowngrade` using `From<T> for Self`
+/// - `shortcut(A, B)`: direct upgrades, same as in `def_schema!`
+/// - `lossless` or `lossy`: implements `Schema::lossiness`
+///
+/// When `prev` or `next` is omitted, `SchemaUpgrade` or `SchemaDowngrade` should be implemented manually.
+/// Versions of neighbours are checked at compile time.
+///
+/// Generic types are supported too, but all bounds required by `Schema`
+/// (including `Serialize` and `Deserialize` for `serde`) must be specified on the type itself.
+#[proc_macro_derive(Schema, attributes(schema))]
+pub fn derive_schema(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    match expand(input) {
+        Ok(res) => res.into(),
+        Err(e) => e.to_compile_error().into(),
+    }
+}
+
+fn expand(input: DeriveInput) -> syn::Result<TokenStream2> {
+    let args = SchemaArgs::from_input(&input)?;
+    let krate = quote!(::lmtreedb::schema);
+    let name = &input.ident;
+    let is_generic = !input.generics.params.is_empty();
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+
+    let version_lit = match &args.version {
+        Some(v) => v,
+        None => {
+            return Err(syn::Error::new_spanned(
+                &input.ident,
+                "Missing `#[schema(version = N)]` attribute",
+            ))
+        }
+    };
+    let version: u64 = version_lit.base10_parse()?;
+    if version == 0 {
+        return Err(syn::Error::new_spanned(version_lit, "Version '0' is not allowed"));
+    }
+    let first = version == 1;
+    let last = args.last;
+
+    if let (true, Some(prev)) = (first, &args.prev) {
+        return Err(syn::Error::new_spanned(prev, "First version cannot have `prev`"));
+    }
+    if let (true, Some(next)) = (last, &args.next) {
+        return Err(syn::Error::new_spanned(next, "Last version cannot have `next`"));
+    }
+
+    let mut items = Vec::new();
+
+    // Markers and version itself
+    match (first, last) {
+        (true, true) => items.push(quote! {
+            impl #impl_generics #krate::SingleVersionMarker for #name #ty_generics #where_clause {}
+        }),
+        (true, false) => items.push(quote! {
+            impl #impl_generics #krate::FirstVersionMarker for #name #ty_generics #where_clause {}
+        }),
+        (false, _) => {
+            if last {
+                items.push(quote! {
+                    impl #impl_generics #krate::LastVersionMarker for #name #ty_generics #where_clause {}
+                });
+            }
+            items.push(quote! {
+                impl #impl_generics #krate::SchemaVersion for #name #ty_generics #where_clause {
+                    const VERSION: u64 = #version;
+                }
+            });
+        }
+    }
+
+    if let Some(prev) = &args.prev {
+        items.push(quote! {
+            impl #impl_generics #krate::SchemaUpgrade for #name #ty_generics #where_clause {
+                type PrevVersion = #prev;
+                fn upgrade(val: Self::PrevVersion) -> Result<Self, #krate::my_error::Error> {
+                    Ok(<Self as ::std::convert::From<#prev>>::from(val))
+                }
+            }
+        });
+    }
+
+    if let Some(next) = &args.next {
+        items.push(quote! {
+            impl #impl_generics #krate::SchemaDowngrade for #name #ty_generics #where_clause {
+                type NextVersion = #next;
+                fn downgrade(val: Self::NextVersion) -> Result<Self, #krate::my_error::Error> {
+                    Ok(<Self as ::std::convert::From<#next>>::from(val))
+                }
+            }
+        });
+    }
+
+    if args.serde {
+        items.push(quote! {
+            impl #impl_generics #krate::SchemaSerdeMarker for #name #ty_generics #where_clause {}
+        });
+    }
+
+    // Same checks as in `def_schema!`
+    let mut checks = Vec::new();
+    if !first {
+        checks.push((
+            quote!(<Self as #krate::SchemaVersion>::VERSION),
+            quote!(<<Self as #krate::SchemaUpgrade>::PrevVersion as #krate::SchemaVersion>::VERSION + 1),
+            "Version of PrevVersion must be one less than version of this type",
+        ));
+    }
+    if !last {
+        checks.push((
+            quote!(<Self as #krate::SchemaVersion>::VERSION),
+            quote!(<<Self as #krate::SchemaDowngrade>::NextVersion as #krate::SchemaVersion>::VERSION - 1),
+            "Version of NextVersion must be one greater than version of this type",
+        ));
+    }
+    for sc in &args.shortcuts {
+        checks.push((
+            quote!(<#sc as #krate::SchemaVersion>::VERSION < <Self as #krate::SchemaVersion>::VERSION),
+            quote!(true),
+            "Shortcuts are only for upgrades",
+        ));
+    }
+
+    let mut schema_body = Vec::new();
+    if !args.shortcuts.is_empty() {
+        let shortcuts = &args.shortcuts;
+        schema_body.push(quote! {
+            fn shortcut(version: u64) -> Option<#krate::Shortcut<Self>> {
+                #(
+                    if version == <#shortcuts as #krate::SchemaVersion>::VERSION {
+                        return Some(#krate::shortcut::<#shortcuts, Self>);
+                    }
+                )*
+                None
+            }
+        });
+    }
+
+    if let Some(lossiness) = &args.lossiness {
+        schema_body.push(quote! {
+            fn lossiness() -> #krate::Lossiness {
+                #krate::Lossiness::#lossiness
+            }
+        });
+    }
+
+    if !is_generic {
+        // `Self` cannot be used outside of impl, so replace it with the type itself
+        for (left, right, _) in &checks {
+            let left = replace_self(left.clone(), name);
+            let right = replace_self(right.clone(), name);
+            items.push(quote! {
+                #krate::static_assertions::const_assert_eq!(#left, #right);
+            });
+        }
+    } else if !checks.is_empty() {
+        // Static assertions cannot use generic parameters, so checks are done by associated constant.
+        // It is evaluated after monomorphization, when `version()` is used.
+        let asserts = checks.iter().map(|(left, right, msg)| {
+            quote! {
+                assert!(#left == #right, #msg);
+            }
+        });
+        items.push(quote! {
+            impl #impl_generics #name #ty_generics #where_clause {
+                #[doc(hidden)]
+                const __LMTREEDB_SCHEMA_CHECK: () = {
+                    #(#asserts)*
+                };
+            }
+        });
+        schema_body.push(quote! {
+            fn version() -> u64 {
+                #[allow(clippy::let_unit_value)]
+                let _: () = Self::__LMTREEDB_SCHEMA_CHECK;
+                <Self as #krate::SchemaVersion>::VERSION
+            }
+        });
+    }
+
+    items.push(quote! {
+        impl #impl_generics #krate::Schema for #name #ty_generics #where_clause {
+            #(#schema_body)*
+        }
+    });
+
+    Ok(quote! {
+        #(#items)*
+    })
+}
+
+/// Replaces all `Self` tokens with the given type name
+fn replace_self(tokens: TokenStream2, name: &Ident) -> TokenStream2 {
+    use proc_macro2::{Group, TokenTree};
+    tokens
+        .into_iter()
+        .map(|tt| match tt {
+            TokenTree::Ident(ident) if ident == "Self" => TokenTree::Ident(name.clone()),
+            TokenTree::Group(group) => {
+                let mut res = Group::new(group.delimiter(), replace_self(group.stream(), name));
+                res.set_span(group.span());
+                TokenTree::Group(res)
+            }
+            other => other,
+        })
+        .collect()
+}
@@ -105,19 +105,11 @@ impl CdPath {
     fn non_auto(self) -> CdPath {
         match self {
             CdPath::Auto(auto) => {
-                let path: Vec<&str> = auto.split('/').collect();
-                match &path[..] {
-                    ["."] => CdPath::Current,
-                    [".."] => CdPath::Up,
-                    _ => {
-                        let root = Root::default().path().to_string();
-                        let p = Path(path.into_iter().map(|x| x.to_string()).collect());
-                        if auto.starts_with('/') || auto.starts_with(&root) {
-                            CdPath::Absolute(p)
-                        } else {
-                            CdPath::Relative(p)
-                        }
-                    }
+                let path = Path::parse(&auto);
+                if path.is_absolute() {
+                    CdPath::Absolute(path)
+                } else {
+                    CdPath::Relative(path)
                 }
             }
             other => other,
@@ -142,11 +134,12 @@ impl FileBrowser {
         let orig = self.path.clone();
         match path.non_auto() {
             CdPath::Auto(_) => unreachable!(),
-            CdPath::Relative(rel) => self.path += rel,
+            CdPath::Relative(rel) => self.path = self.path.join(&rel),
             CdPath::Absolute(abs) => self.path = abs,
             CdPath::Up => {
-                if self.path.0.len() != 1 {
-                    self.path.0.pop();
+                // Parent of the roots is the list of roots
+                if !self.path.is_empty() {
+                    self.path.truncate(self.path.len() - 1);
                 }
             }
             CdPath::Selected => {
@@ -163,7 +156,7 @@ impl FileBrowser {
     }
 
     fn ls(&mut self) -> Result<(), Error> {
-        let info = self.storage.children(&self.path).epos(pos!())?;
+        let info = self.storage.children(&self.path).cast(pos!())?;
         let info: DataWrapperV1 = info.err(pos!())?;
 
         self.file_info = format!(
@@ -189,13 +182,13 @@ impl FileBrowser {
                 CdPath::Up
             } else {
                 let p = p.clone();
-                CdPath::Relative(Path(vec![p]))
+                CdPath::Relative(Path::from_parts(&[p]))
             }
         } else {
             CdPath::Current
         };
         let resolved = match res.clone().non_auto() {
-            CdPath::Relative(rel) => self.path.clone() + rel,
+            CdPath::Relative(rel) => self.path.join(&rel),
             CdPath::Absolute(abs) => abs,
             CdPath::Auto(_) => unreachable!(),
             CdPath::Selected => unreachable!(),
@@ -208,7 +201,7 @@ impl FileBrowser {
     fn write(&mut self, path: CdPath) -> Result<(), Error> {
         let path = match path.non_auto() {
             CdPath::Auto(_) => unreachable!(),
-            CdPath::Relative(rel) => self.path.clone() + rel,
+            CdPath::Relative(rel) => self.path.join(&rel),
             CdPath::Absolute(abs) => abs,
             CdPath::Up => self.path.pop().0,
             CdPath::Selected => {
@@ -216,7 +209,7 @@ impl FileBrowser {
             }
             CdPath::Current => self.path.clone(),
         };
-        self.storage.put(&path, ()).epos(pos!())?;
+        self.storage.put(&path, ()).cast(pos!())?;
         self.ls().epos(pos!())?;
         Ok(())
     }
@@ -225,7 +218,7 @@ impl FileBrowser {
         let path = self.selected_path();
         match path.0 {
             CdPath::Relative(_) | CdPath::Absolute(_) => {
-                self.storage.del(&path.1).epos(pos!())?;
+                self.storage.del(&path.1).cast(pos!())?;
             }
             CdPath::Current | CdPath::Up => {
                 return Err(err!("Cannot remove current or parent file"))
@@ -238,7 +231,7 @@ impl FileBrowser {
 
     fn read_dbg(&mut self) -> Result<(), Error> {
         let path = self.selected_path().1;
-        let info = self.storage.children(&path).epos(pos!())?;
+        let info = self.storage.children(&path).cast(pos!())?;
         let info: DataWrapperV1 = info.err(pos!())?;
 
         self.info_title = self.path.to_string();
@@ -437,7 +430,7 @@ impl App {
                 info: String::new(),
                 info_title: "Info".to_string(),
                 files: Vec::new(),
-                storage: Storage::connect(path)?,
+                storage: Storage::connect(path).cast(pos!())?,
             },
             state: AppState::Running,
         };
@@ -0,0 +1,256 @@
+//! `Schema` implementations for std collections.
+//!
+//! All of them have single version, but items are saved together with their version
+//! and upgraded or downgraded one by one while loading.
+//! The only exception is `Vec<T>`: its first version is the plain array `Vec<u8>` was stored as.
+
+use std::collections::{BTreeMap, HashMap};
+use std::convert::TryFrom;
+use std::iter::FromIterator;
+
+use rmpv::Value;
+
+use crate::*;
+use my_error::Error;
+
+use super::load;
+
+/// Saves all items as `[version, [items...]]`. All items have the same version, so it is stored once.
+fn save_items<T: Schema, I: IntoIterator<Item = T>>(items: I) -> Result<Value, Error> {
+    let items: Result<Vec<Value>, Error> = items.into_iter().map(|x| x.save()).collect();
+    let items = items.epos(pos!())?;
+    Ok(Value::Array(vec![Value::from(T::version()), Value::Array(items)]))
+}
+
+/// Loads items saved by `save_items`.
+fn load_items<T: Schema>(val: Value) -> Result<Vec<T>, Error> {
+    let mut arr = match val {
+        Value::Array(arr) => arr,
+        other => return Err(err!("Invalid format: {}", other)),
+    };
+    if arr.len() != 2 {
+        return Err(err!("Invalid format"));
+    }
+    let items = match arr.pop() {
+        Some(Value::Array(items)) => items,
+        other => return Err(err!("Invalid format: {:?}", other)),
+    };
+    let version = arr[0].as_u64().err(pos!())?;
+    items
+        .into_iter()
+        .map(|x| load(version, x).epos(pos!(version)))
+        .collect()
+}
+
+/// Saves map as `[version, {key: value...}]`, like `save_items`
+fn save_map<T: Schema, I: IntoIterator<Item = (String, T)>>(items: I) -> Result<Value, Error> {
+    let mut res = Vec::new();
+    for (k, v) in items {
+        res.push((Value::from(k), v.save().epos(pos!())?));
+    }
+    Ok(Value::Array(vec![Value::from(T::version()), Value::Map(res)]))
+}
+
+/// Loads map saved by `save_map`
+fn load_map<T: Schema, C: FromIterator<(String, T)>>(val: Value) -> Result<C, Error> {
+    let mut arr = match val {
+        Value::Array(arr) => arr,
+        other => return Err(err!("Invalid format: {}", other)),
+    };
+    if arr.len() != 2 {
+        return Err(err!("Invalid format"));
+    }
+    let items = match arr.pop() {
+        Some(Value::Map(items)) => items,
+        other => return Err(err!("Invalid format: {:?}", other)),
+    };
+    let version = arr[0].as_u64().err(pos!())?;
+    items
+        .into_iter()
+        .map(|(k, v)| {
+            let k = match k {
+                Value::String(k) => k.into_str().err(pos!())?,
+                other => return Err(err!("Invalid key: {}", other)),
+            };
+            let v = load(version, v).epos(pos!(k, version))?;
+            Ok((k, v))
+        })
+        .collect()
+}
+
+impl<T: Schema> SingleVersionMarker for Option<T> {}
+impl<T: Schema> Schema for Option<T> {}
+
+impl<T: Schema> SchemaSerde for Option<T> {
+    fn load(val: Value) -> Result<Self, Error> {
+        match val {
+            Value::Nil => Ok(None),
+            other => Ok(Some(load_versioned(other).epos(pos!())?)),
+        }
+    }
+
+    fn save(self) -> Result<Value, Error> {
+        match self {
+            None => Ok(Value::Nil),
+            Some(val) => save_versioned(val),
+        }
+    }
+}
+
+/// First version of `Vec<T>`: plain array of items without their version.
+///
+/// Only `Vec<u8>` was stored so, all its items are the first version of `u8`.
+#[derive(Debug)]
+pub struct VecV1<T>(pub Vec<T>);
+
+impl<T: Schema> FirstVersionMarker for VecV1<T> {}
+impl<T: Schema> Schema for VecV1<T> {}
+
+impl<T: Schema> SchemaSerde for VecV1<T> {
+    fn load(val: Value) -> Result<Self, Error> {
+        let items = match val {
+            Value::Array(items) => items,
+            other => return Err(err!("Invalid format: {}", other)),
+        };
+        let items: Result<Vec<T>, Error> = items.into_iter().map(|x| load(1, x)).collect();
+        Ok(VecV1(items.epos(pos!())?))
+    }
+
+    fn save(self) -> Result<Value, Error> {
+        if T::version() != 1 {
+            return Err(err!("Only the first version of items can be saved without version"));
+        }
+        let items: Result<Vec<Value>, Error> = self.0.into_iter().map(|x| x.save()).collect();
+        Ok(Value::Array(items.epos(pos!())?))
+    }
+}
+
+impl<T: Schema> SchemaDowngrade for VecV1<T> {
+    type NextVersion = Vec<T>;
+    fn downgrade(val: Vec<T>) -> Result<Self, Error> {
+        Ok(VecV1(val))
+    }
+}
+
+impl<T: Schema> LastVersionMarker for Vec<T> {}
+impl<T: Schema> Schema for Vec<T> {
+    fn lossiness() -> Lossiness {
+        Lossiness::Lossless
+    }
+}
+
+impl<T: Schema> SchemaVersion for Vec<T> {
+    const VERSION: u64 = 2;
+}
+
+impl<T: Schema> SchemaUpgrade for Vec<T> {
+    type PrevVersion = VecV1<T>;
+    fn upgrade(val: VecV1<T>) -> Result<Self, Error> {
+        Ok(val.0)
+    }
+}
+
+impl<T: Schema> SchemaSerde for Vec<T> {
+    fn load(val: Value) -> Result<Self, Error> {
+        load_items(val)
+    }
+
+    fn save(self) -> Result<Value, Error> {
+        save_items(self)
+    }
+}
+
+/// Arrays are stored the same way as `Vec<T>`
+impl<T: Schema, const N: usize> LastVersionMarker for [T; N] {}
+impl<T: Schema, const N: usize> Schema for [T; N] {}
+
+impl<T: Schema, const N: usize> SchemaVersion for [T; N] {
+    const VERSION: u64 = 2;
+}
+
+impl<T: Schema, const N: usize> SchemaUpgrade for [T; N] {
+    type PrevVersion = VecV1<T>;
+    fn upgrade(val: VecV1<T>) -> Result<Self, Error> {
+        to_array(val.0)
+    }
+}
+
+fn to_array<T, const N: usize>(items: Vec<T>) -> Result<[T; N], Error> {
+    let len = items.len();
+    <[T; N]>::try_from(items).map_err(|_| err!("Invalid length: {} instead of {}", len, N))
+}
+
+impl<T: Schema, const N: usize> SchemaSerde for [T; N] {
+    fn load(val: Value) -> Result<Self, Error> {
+        to_array(load_items(val).epos(pos!())?)
+    }
+
+    fn save(self) -> Result<Value, Error> {
+        save_items(Vec::from(self))
+    }
+}
+
+impl<T: Schema> SingleVersionMarker for HashMap<String, T> {}
+impl<T: Schema> Schema for HashMap<String, T> {}
+
+impl<T: Schema> SchemaSerde for HashMap<String, T> {
+    fn load(val: Value) -> Result<Self, Error> {
+        load_map(val)
+    }
+
+    fn save(self) -> Result<Value, Error> {
+        save_map(self)
+    }
+}
+
+impl<T: Schema> SingleVersionMarker for BTreeMap<String, T> {}
+impl<T: Schema> Schema for BTreeMap<String, T> {}
+
+impl<T: Schema> SchemaSerde for BTreeMap<String, T> {
+    fn load(val: Value) -> Result<Self, Error> {
+        load_map(val)
+    }
+
+    fn save(self) -> Result<Value, Error> {
+        save_map(self)
+    }
+}
+
+/// Tuples are saved as array of versioned items, because all of them have different types.
+macro_rules! tuple_schema {
+    ($($name:ident: $idx:tt),+) => {
+        impl<$($name: Schema),+> SingleVersionMarker for ($($name,)+) {}
+        impl<$($name: Schema),+> Schema for ($($name,)+) {}
+
+        impl<$($name: Schema),+> SchemaSerde for ($($name,)+) {
+            fn load(val: Value) -> Result<Self, Error> {
+                let arr = match val {
+                    Value::Array(arr) => arr,
+                    other => return Err(err!("Invalid format: {}", other)),
+                };
+                let len = [$($idx),+].len();
+                if arr.len() != len {
+                    return Err(err!("Invalid length: {} instead of {}", arr.len(), len));
+                }
+                let mut iter = arr.into_iter();
+                Ok(($(
+                    load_versioned::<$name>(iter.next().err(pos!())?).epos(pos!($idx))?,
+                )+))
+            }
+
+            fn save(self) -> Result<Value, Error> {
+                Ok(Value::Array(vec![$(
+                    save_versioned(self.$idx).epos(pos!($idx))?
+                ),+]))
+            }
+        }
+    };
+}
+
+tuple_schema!(A: 0);
+tuple_schema!(A: 0, B: 1);
+tuple_schema!(A: 0, B: 1, C: 2);
+tuple_schema!(A: 0, B: 1, C: 2, D: 3);
+tuple_schema!(A: 0, B: 1, C: 2, D: 3, E: 4);
+tuple_schema!(A: 0, B: 1, C: 2, D: 3, E: 4, F: 5);
+
@@ -0,0 +1,84 @@
+//! Per-path schema constraints, see `StorageOptions::constraints`.
+//!
+//! ```ignore
+//! let options = StorageOptions {
+//!     constraints: vec![Constraint::new::<UserRecord>("users/*")?.versions(2..)],
+//!     ..Default::default()
+//! };
+//! ```
+
+use std::ops::{Bound, RangeBounds};
+
+use my_error::*;
+
+use crate::path::glob::Glob;
+use crate::path::Path;
+use crate::schema::chain::{describe, Chain};
+use crate::schema::Schema;
+
+/// Rule that all values at pathes matching the glob pattern belong to the schema family of `F`
+#[derive(Clone, Debug)]
+pub struct Constraint {
+    glob: Glob,
+    family: Chain,
+    expected: &'static str,
+    versions: (Bound<u64>, Bound<u64>),
+}
+
+impl Constraint {
+    /// Values at `pattern` must be one of the versions of `F`. See `path::glob` for the pattern syntax.
+    pub fn new<F: Schema>(pattern: &str) -> Result<Constraint, Error> {
+        Ok(Constraint {
+            glob: Glob::parse(pattern).epos(pos!())?,
+            family: describe::<F>(),
+            expected: std::intrinsics::type_name::<F>(),
+            versions: (Bound::Unbounded, Bound::Unbounded),
+        })
+    }
+
+    /// Also restricts versions of the values, for example `2..` forbids writing the first version
+    pub fn versions<R: RangeBounds<u64>>(mut self, range: R) -> Self {
+        self.versions = (range.start_bound().cloned(), range.end_bound().cloned());
+        self
+    }
+
+    /// Checks `T` if `path` is covered by this constraint
+    fn check<T: Schema>(&self, path: &Path) -> Result<(), crate::Error> {
+        if !self.glob.matches(path) {
+            return Ok(());
+        }
+
+        let version = T::version();
+        let in_family = matches!(
+            self.family.get(version),
+            Some(x) if x.type_name == std::intrinsics::type_name::<T>()
+        );
+        let trace = if !in_family {
+            err!(
+                "{} is not a version of {}",
+                std::intrinsics::type_name::<T>(),
+                self.expected
+            )
+        } else if !self.versions.contains(&version) {
+            err!("Version {} of {} is not allowed", version, self.expected)
+        } else {
+            return Ok(());
+        };
+        Err(crate::Error::TypeMismatch {
+            path: path.clone(),
+            expected: self.expected,
+            trace,
+        })
+    }
+}
+
+/// Checks all constraints covering `path`
+pub(crate) fn check<T: Schema>(
+    constraints: &[Constraint],
+    path: &Path,
+) -> Result<(), crate::Error> {
+    for constraint in constraints {
+        constraint.check::<T>(path)?;
+    }
+    Ok(())
+}
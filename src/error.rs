@@ -1,7 +1,9 @@
-use inheritance::{inheritable, Inheritance};
+use std::backtrace::Backtrace;
 use std::fmt::{Debug, Display, Formatter};
+use std::sync::atomic::{AtomicBool, Ordering};
 
 /// Position of occurred error. Best used with the `pos!` macro
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub struct ErrorPosition {
     pub file: &'static str,
     pub line: u32,
@@ -30,6 +32,15 @@ impl Display for ErrorPosition {
     }
 }
 
+static CAPTURE_BACKTRACES: AtomicBool = AtomicBool::new(false);
+
+/// Enables capturing of backtraces by `Error::msg`, `err!` and conversions from other errors.
+///
+/// Capturing is slow, so it is disabled by default.
+pub fn capture_backtraces(enabled: bool) {
+    CAPTURE_BACKTRACES.store(enabled, Ordering::Relaxed);
+}
+
 /// Type for all critical errors that should be bubbled up.
 pub struct Error {
     message: Option<String>,
@@ -37,24 +48,33 @@ pub struct Error {
 
     /// Reference to the error, which caused this error to happen.
     previous: Option<Box<Error>>,
+
+    /// Captured when the chain is started, see `capture_backtraces`. Boxed to keep errors small.
+    backtrace: Option<Box<Backtrace>>,
 }
 
 impl Error {
     /// Creates new using only position
     pub fn pos(pos: ErrorPosition) -> Self {
-        Self {
-            message: None,
-            position: pos,
-            previous: None,
-        }
+        Self::link(pos, None)
     }
 
     /// Creates new error with both message and position
     pub fn msg(pos: ErrorPosition, msg: String) -> Self {
+        let mut res = Self::link(pos, Some(msg));
+        if CAPTURE_BACKTRACES.load(Ordering::Relaxed) {
+            res.backtrace = Some(Box::new(Backtrace::force_capture()));
+        }
+        res
+    }
+
+    /// Creates new error without backtrace, it is added to the end of existing chain
+    fn link(pos: ErrorPosition, msg: Option<String>) -> Self {
         Self {
-            message: Some(msg),
+            message: msg,
             position: pos,
             previous: None,
+            backtrace: None,
         }
     }
 
@@ -63,6 +83,29 @@ impl Error {
         next.previous = Some(Box::new(self));
         next
     }
+
+    pub fn message(&self) -> Option<&str> {
+        self.message.as_deref()
+    }
+
+    pub fn position(&self) -> &ErrorPosition {
+        &self.position
+    }
+
+    /// Error which caused this one
+    pub fn previous(&self) -> Option<&Error> {
+        self.previous.as_deref()
+    }
+
+    /// Iterates over the chain from this error to the first one
+    pub fn iter(&self) -> impl Iterator<Item = &Error> {
+        std::iter::successors(Some(self), |x| x.previous())
+    }
+
+    /// Backtrace of the first error in the chain, if it was captured
+    pub fn backtrace(&self) -> Option<&Backtrace> {
+        self.iter().filter_map(|x| x.backtrace.as_deref()).last()
+    }
 }
 
 impl Display for Error {
@@ -87,18 +130,69 @@ impl Debug for Error {
     }
 }
 
+/// Serialized as sequence of errors from the first one, the same order as `Display` uses.
+/// Each of them has `file`, `line`, `column`, `context`, `message` and `backtrace` fields.
+#[cfg(feature = "serde")]
+impl serde::Serialize for Error {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        #[derive(serde::Serialize)]
+        struct Frame<'a> {
+            #[serde(flatten)]
+            position: &'a ErrorPosition,
+            message: Option<&'a str>,
+            backtrace: Option<String>,
+        }
+
+        let mut frames: Vec<Frame> = self
+            .iter()
+            .map(|x| Frame {
+                position: &x.position,
+                message: x.message(),
+                backtrace: x.backtrace.as_ref().map(|x| x.to_string()),
+            })
+            .collect();
+        frames.reverse();
+        serializer.collect_seq(frames)
+    }
+}
+
 // Unable to implement std::error::Error directly for Error because of conflict of
 // `impl From<T: std::error::Error> for Error` in this file
 // and `impl From<T> for T` in stdlib
 // FIXME: Maybe #![feature(specialization)] can help some day
-#[derive(Inheritance)]
-pub struct ErrWrapper(#[inherits(Display, Debug)] Error);
+//
+// Each error of the chain is a separate source, so it displays only its own position and message.
+#[repr(transparent)]
+pub struct ErrWrapper(Error);
+
+impl Display for ErrWrapper {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), std::fmt::Error> {
+        match &self.0.message {
+            None => write!(f, "<{}>", self.0.position),
+            Some(msg) => write!(f, "<{}> {}", self.0.position, msg),
+        }
+    }
+}
+
+impl Debug for ErrWrapper {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), std::fmt::Error> {
+        Debug::fmt(&self.0, f)
+    }
+}
 
-impl std::error::Error for ErrWrapper {}
+impl std::error::Error for ErrWrapper {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self.0.previous() {
+            None => None,
+            Some(prev) => Some(AsRef::<ErrWrapper>::as_ref(prev)),
+        }
+    }
+}
 
 impl AsRef<ErrWrapper> for Error {
     fn as_ref(&self) -> &ErrWrapper {
-        &ErrWrapper(*self)
+        // ErrWrapper is `repr(transparent)`, so it has the same layout as Error
+        unsafe { &*(self as *const Error as *const ErrWrapper) }
     }
 }
 
@@ -116,6 +210,19 @@ impl Into<ErrWrapper> for Error {
     }
 }
 
+// Allows to use `?` in functions returning `Box<dyn std::error::Error>`
+impl From<Error> for Box<dyn std::error::Error + Send + Sync> {
+    fn from(err: Error) -> Self {
+        Box::new(ErrWrapper(err))
+    }
+}
+
+impl From<Error> for Box<dyn std::error::Error> {
+    fn from(err: Error) -> Self {
+        Box::new(ErrWrapper(err))
+    }
+}
+
 /// Just an extension trait to plain Result which provides useful things.
 pub trait ChainableResult {
     type Result;
@@ -145,7 +252,7 @@ impl<R, E: Into<Error>> ChainableResult for Result<R, E> {
     {
         match self {
             Ok(val) => Ok(val),
-            Err(err) => Err(err.into().chain(Error::msg(pos(), msg()))),
+            Err(err) => Err(err.into().chain(Error::link(pos(), Some(msg())))),
         }
     }
 
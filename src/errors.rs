@@ -0,0 +1,342 @@
+use std::fmt::{self, Display, Formatter};
+
+use my_error::{err, pos, ErrWrapper, ErrorPosition};
+use serde::ser::{Serialize, SerializeStruct, Serializer};
+
+use crate::path::Path;
+
+/// Position chain of the error, the same one as `epos(pos!())` builds everywhere
+pub type Trace = my_error::Error;
+
+/// Error returned by all `Storage` methods.
+///
+/// Variants can be matched to find out what happened.
+/// Each of them carries `trace` with the positions where error occurred and was passed through.
+///
+/// It implements `std::error::Error`, so `?` converts it to `Box<dyn std::error::Error>`
+/// and to `my_error::Error`. The latter keeps only the text, `into_trace` keeps the positions.
+/// The trace is exposed as a chain of sources, from the last position to the first one.
+///
+/// Serialized form has `kind`, `message`, `path` and `trace` fields, see also `to_json`
+/// and `to_msgpack`. Trace is serialized by `my_error`, as a list of positions from the first one.
+#[derive(Debug)]
+pub enum Error {
+    /// Parent of `path` does not exist, so node cannot be created
+    ParentMissing { path: Path, trace: Trace },
+    /// Node at `path` has children, so it cannot be removed
+    HasChildren { path: Path, trace: Trace },
+    /// There is no node at `path`
+    NotFound { path: Path, trace: Trace },
+    /// Data at `path` is corrupted or cannot be loaded as the requested type
+    Decode { path: Path, trace: Trace },
+    /// Stored version of data at `path` is not reachable from the requested schema
+    VersionChain {
+        path: Path,
+        stored: u64,
+        requested: u64,
+        trace: Trace,
+    },
+    /// Value at `path` has type different from the expected one
+    TypeMismatch {
+        path: Path,
+        expected: &'static str,
+        trace: Trace,
+    },
+    /// `path` points outside of the `Scope` it was passed to
+    OutOfScope { path: Path, trace: Trace },
+    /// Write to `path` was rejected by one of `hooks::Hooks` validators
+    Rejected { path: Path, trace: Trace },
+    /// Failure of LMDB itself, for example `lmdb::Error::MapFull`
+    Lmdb {
+        path: Option<Path>,
+        error: lmdb::Error,
+        trace: Trace,
+    },
+    /// Anything else
+    Other { path: Option<Path>, trace: Trace },
+}
+
+impl Error {
+    /// Path of the node that caused this error, if known
+    pub fn path(&self) -> Option<&Path> {
+        match self {
+            Error::ParentMissing { path, .. }
+            | Error::HasChildren { path, .. }
+            | Error::NotFound { path, .. }
+            | Error::Decode { path, .. }
+            | Error::VersionChain { path, .. }
+            | Error::TypeMismatch { path, .. }
+            | Error::OutOfScope { path, .. }
+            | Error::Rejected { path, .. } => Some(path),
+            Error::Lmdb { path, .. } | Error::Other { path, .. } => path.as_ref(),
+        }
+    }
+
+    pub fn trace(&self) -> &Trace {
+        match self {
+            Error::ParentMissing { trace, .. }
+            | Error::HasChildren { trace, .. }
+            | Error::NotFound { trace, .. }
+            | Error::Decode { trace, .. }
+            | Error::VersionChain { trace, .. }
+            | Error::TypeMismatch { trace, .. }
+            | Error::OutOfScope { trace, .. }
+            | Error::Rejected { trace, .. }
+            | Error::Lmdb { trace, .. }
+            | Error::Other { trace, .. } => trace,
+        }
+    }
+
+    /// Name of the variant, for example `NotFound`
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Error::ParentMissing { .. } => "ParentMissing",
+            Error::HasChildren { .. } => "HasChildren",
+            Error::NotFound { .. } => "NotFound",
+            Error::Decode { .. } => "Decode",
+            Error::VersionChain { .. } => "VersionChain",
+            Error::TypeMismatch { .. } => "TypeMismatch",
+            Error::OutOfScope { .. } => "OutOfScope",
+            Error::Rejected { .. } => "Rejected",
+            Error::Lmdb { .. } => "Lmdb",
+            Error::Other { .. } => "Other",
+        }
+    }
+
+    /// Renders error as a single line JSON object with the same fields as the serialized one
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).expect("Error is always serializable")
+    }
+
+    /// Renders error as MessagePack map with the same fields as the serialized one
+    pub fn to_msgpack(&self) -> Vec<u8> {
+        rmp_serde::to_vec_named(self).expect("Error is always serializable")
+    }
+
+    /// Drops the kind of error, leaving only position chain with the description at the end
+    pub fn into_trace(self) -> Trace {
+        let msg = self.describe();
+        self.take_trace().chain(err!("{}", msg))
+    }
+
+    /// Adds position to the trace, keeping the kind of error
+    pub fn epos<F>(self, pos: F) -> Self
+    where
+        F: FnOnce() -> ErrorPosition,
+    {
+        self.map_trace(|trace| trace.chain(Trace::pos(pos())))
+    }
+
+    /// Sets path for `Lmdb` and `Other` errors if it is unknown yet
+    pub fn at(self, at: &Path) -> Self {
+        match self {
+            Error::Lmdb {
+                path: None,
+                error,
+                trace,
+            } => Error::Lmdb {
+                path: Some(at.clone()),
+                error,
+                trace,
+            },
+            Error::Other { path: None, trace } => Error::Other {
+                path: Some(at.clone()),
+                trace,
+            },
+            other => other,
+        }
+    }
+
+    fn take_trace(self) -> Trace {
+        match self {
+            Error::ParentMissing { trace, .. }
+            | Error::HasChildren { trace, .. }
+            | Error::NotFound { trace, .. }
+            | Error::Decode { trace, .. }
+            | Error::VersionChain { trace, .. }
+            | Error::TypeMismatch { trace, .. }
+            | Error::OutOfScope { trace, .. }
+            | Error::Rejected { trace, .. }
+            | Error::Lmdb { trace, .. }
+            | Error::Other { trace, .. } => trace,
+        }
+    }
+
+    fn map_trace<F>(self, f: F) -> Self
+    where
+        F: FnOnce(Trace) -> Trace,
+    {
+        match self {
+            Error::ParentMissing { path, trace } => Error::ParentMissing {
+                path,
+                trace: f(trace),
+            },
+            Error::HasChildren { path, trace } => Error::HasChildren {
+                path,
+                trace: f(trace),
+            },
+            Error::NotFound { path, trace } => Error::NotFound {
+                path,
+                trace: f(trace),
+            },
+            Error::Decode { path, trace } => Error::Decode {
+                path,
+                trace: f(trace),
+            },
+            Error::VersionChain {
+                path,
+                stored,
+                requested,
+                trace,
+            } => Error::VersionChain {
+                path,
+                stored,
+                requested,
+                trace: f(trace),
+            },
+            Error::TypeMismatch {
+                path,
+                expected,
+                trace,
+            } => Error::TypeMismatch {
+                path,
+                expected,
+                trace: f(trace),
+            },
+            Error::OutOfScope { path, trace } => Error::OutOfScope {
+                path,
+                trace: f(trace),
+            },
+            Error::Rejected { path, trace } => Error::Rejected {
+                path,
+                trace: f(trace),
+            },
+            Error::Lmdb { path, error, trace } => Error::Lmdb {
+                path,
+                error,
+                trace: f(trace),
+            },
+            Error::Other { path, trace } => Error::Other {
+                path,
+                trace: f(trace),
+            },
+        }
+    }
+
+    /// Short human readable description without trace
+    fn describe(&self) -> String {
+        match self {
+            Error::ParentMissing { path, .. } => format!("No parent found for '{}'", path),
+            Error::HasChildren { path, .. } => format!("Cannot del '{}' with children", path),
+            Error::NotFound { path, .. } => format!("'{}' is not found", path),
+            Error::Decode { path, .. } => format!("Unable to decode '{}'", path),
+            Error::VersionChain {
+                path,
+                stored,
+                requested,
+                ..
+            } => format!(
+                "Version {} of '{}' cannot be converted to {}",
+                stored, path, requested
+            ),
+            Error::TypeMismatch { path, expected, .. } => {
+                format!("'{}' is expected to be {}", path, expected)
+            }
+            Error::OutOfScope { path, .. } => format!("'{}' is outside of the scope", path),
+            Error::Rejected { path, .. } => format!("Write to '{}' is rejected", path),
+            Error::Lmdb {
+                path: Some(path),
+                error,
+                ..
+            } => format!("LMDB error at '{}': {}", path, error),
+            Error::Lmdb {
+                path: None, error, ..
+            } => format!("LMDB error: {}", error),
+            Error::Other { path: Some(path), .. } => format!("Failed at '{}'", path),
+            Error::Other { path: None, .. } => "Failed".to_string(),
+        }
+    }
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "{}: {}", self.describe(), self.trace())
+    }
+}
+
+/// Source is the last position of the trace, its source is the previous one and so on.
+/// The original `lmdb::Error` is kept in the `error` field of `Error::Lmdb`.
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(AsRef::<ErrWrapper>::as_ref(self.trace()))
+    }
+}
+
+impl Serialize for Error {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut res = serializer.serialize_struct("Error", 4)?;
+        res.serialize_field("kind", self.kind())?;
+        res.serialize_field("message", &self.describe())?;
+        res.serialize_field("path", &self.path())?;
+        res.serialize_field("trace", self.trace())?;
+        res.end()
+    }
+}
+
+impl From<Trace> for Error {
+    fn from(trace: Trace) -> Self {
+        Error::Other { path: None, trace }
+    }
+}
+
+/// Adds position to the results with `lmtreedb::Error`, keeping the kind of error.
+///
+/// `ChainableResult::epos` also accepts them, but it converts the error to `my_error::Error`.
+pub(crate) trait ResultExt<R> {
+    fn trace_pos<F>(self, pos: F) -> Result<R, Error>
+    where
+        F: FnOnce() -> ErrorPosition;
+}
+
+impl<R> ResultExt<R> for Result<R, Error> {
+    fn trace_pos<F>(self, pos: F) -> Result<R, Error>
+    where
+        F: FnOnce() -> ErrorPosition,
+    {
+        self.map_err(|e| e.epos(pos))
+    }
+}
+
+/// Converts LMDB errors to `Error::Lmdb`
+pub(crate) trait LmdbResultExt<R> {
+    fn lmdb<F>(self, path: Option<&Path>, pos: F) -> Result<R, Error>
+    where
+        F: FnOnce() -> ErrorPosition;
+}
+
+impl<R> LmdbResultExt<R> for Result<R, lmdb::Error> {
+    fn lmdb<F>(self, path: Option<&Path>, pos: F) -> Result<R, Error>
+    where
+        F: FnOnce() -> ErrorPosition,
+    {
+        self.map_err(|error| Error::Lmdb {
+            path: path.cloned(),
+            trace: Trace::msg(pos(), error.to_string()),
+            error,
+        })
+    }
+}
+
+/// Gives kind to the plain `my_error::Error`
+pub(crate) trait TraceResultExt<R> {
+    fn decode(self, path: &Path) -> Result<R, Error>;
+}
+
+impl<R> TraceResultExt<R> for Result<R, Trace> {
+    fn decode(self, path: &Path) -> Result<R, Error> {
+        self.map_err(|trace| Error::Decode {
+            path: path.clone(),
+            trace,
+        })
+    }
+}
@@ -0,0 +1,283 @@
+use rmpv::Value;
+use serde::de::{self, Deserialize, Deserializer, Visitor};
+
+use crate::*;
+use my_error::Error;
+
+/// Single change of fields between version and its previous version.
+///
+/// Changes are applied to the map of field names to values, see `SchemaFields`.
+#[derive(Clone, Debug)]
+pub enum FieldOp {
+    /// Field is added in this version. Default value is used while upgrading.
+    Add { name: &'static str, default: Value },
+    /// Field is removed in this version. Default value is used to restore it while downgrading.
+    Remove { name: &'static str, default: Value },
+    /// Field is renamed in this version.
+    Rename { from: &'static str, to: &'static str },
+}
+
+impl FieldOp {
+    /// Converts map of the previous version to the current.
+    fn apply(&self, map: &mut Vec<(Value, Value)>) {
+        match self {
+            FieldOp::Add { name, default } => insert(map, name, default),
+            FieldOp::Remove { name, .. } => remove(map, name),
+            FieldOp::Rename { from, to } => rename(map, from, to),
+        }
+    }
+
+    /// Converts map of the current version back to the previous.
+    fn revert(&self, map: &mut Vec<(Value, Value)>) {
+        match self {
+            FieldOp::Add { name, .. } => remove(map, name),
+            FieldOp::Remove { name, default } => insert(map, name, default),
+            FieldOp::Rename { from, to } => rename(map, to, from),
+        }
+    }
+}
+
+fn insert(map: &mut Vec<(Value, Value)>, name: &str, default: &Value) {
+    if !map.iter().any(|(k, _)| k.as_str() == Some(name)) {
+        map.push((Value::from(name), default.clone()));
+    }
+}
+
+fn remove(map: &mut Vec<(Value, Value)>, name: &str) {
+    map.retain(|(k, _)| k.as_str() != Some(name));
+}
+
+fn rename(map: &mut [(Value, Value)], from: &str, to: &str) {
+    for (k, _) in map.iter_mut() {
+        if k.as_str() == Some(from) {
+            *k = Value::from(to);
+        }
+    }
+}
+
+/// Describes serde version as a set of field changes of its previous version.
+///
+/// Implemented by `def_fields!` macro, which also implements `SchemaUpgrade` for this type
+/// and `SchemaDowngrade` for the previous one.
+pub trait SchemaFields: SchemaSerdeMarker {
+    type Prev: SchemaSerdeMarker;
+
+    /// All changes in order of applying
+    fn fields() -> Result<Vec<FieldOp>, Error>;
+}
+
+/// Converts previous version to the current one by applying all changes.
+pub fn upgrade<T: SchemaFields>(val: T::Prev) -> Result<T, Error> {
+    let mut map = to_map(val).epos(pos!())?;
+    for op in T::fields().epos(pos!())? {
+        op.apply(&mut map);
+    }
+    let res = rmpv::ext::from_value(Value::Map(map)).epos(pos!())?;
+    Ok(res)
+}
+
+/// Converts current version to the previous one by reverting all changes in reverse order.
+pub fn downgrade<T: SchemaFields>(val: T) -> Result<T::Prev, Error> {
+    let mut map = to_map(val).epos(pos!())?;
+    for op in T::fields().epos(pos!())?.iter().rev() {
+        op.revert(&mut map);
+    }
+    let res = rmpv::ext::from_value(Value::Map(map)).epos(pos!())?;
+    Ok(res)
+}
+
+/// Serializes any value to use it as default in `FieldOp`
+pub fn value<T: serde::Serialize>(val: T) -> Result<Value, Error> {
+    let res = rmpv::ext::to_value(val).epos(pos!())?;
+    Ok(res)
+}
+
+/// Serializes struct to the map of field names to values.
+///
+/// Structs are serialized to arrays, so names are taken from their `Deserialize` implementation.
+fn to_map<T: SchemaSerdeMarker>(val: T) -> Result<Vec<(Value, Value)>, Error> {
+    match rmpv::ext::to_value(val).epos(pos!())? {
+        Value::Map(map) => Ok(map),
+        Value::Array(arr) => {
+            let names = field_names::<T>();
+            if names.len() != arr.len() {
+                return Err(err!(
+                    "Unable to find names of fields: {:?}, but got {} values",
+                    names,
+                    arr.len()
+                ));
+            }
+            Ok(names.iter().map(|&x| Value::from(x)).zip(arr).collect())
+        }
+        other => Err(err!("Only structs are supported, found: {}", other)),
+    }
+}
+
+/// Returns names of fields as they are passed to `Deserializer::deserialize_struct`.
+///
+/// Empty slice is returned for non-structs.
+fn field_names<T: for<'de> Deserialize<'de>>() -> &'static [&'static str] {
+    struct Introspect<'a>(&'a mut &'static [&'static str]);
+
+    impl<'de, 'a> Deserializer<'de> for Introspect<'a> {
+        type Error = de::value::Error;
+
+        fn deserialize_any<V: Visitor<'de>>(self, _: V) -> Result<V::Value, Self::Error> {
+            Err(de::Error::custom("not a struct"))
+        }
+
+        fn deserialize_struct<V: Visitor<'de>>(
+            self,
+            _name: &'static str,
+            fields: &'static [&'static str],
+            _visitor: V,
+        ) -> Result<V::Value, Self::Error> {
+            *self.0 = fields;
+            Err(de::Error::custom("fields are found, nothing to deserialize"))
+        }
+
+        serde::forward_to_deserialize_any! {
+            bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+            bytes byte_buf option unit unit_struct newtype_struct seq tuple
+            tuple_struct map enum identifier ignored_any
+        }
+    }
+
+    let mut res: &'static [&'static str] = &[];
+    // It always fails, we need only side effect
+    let _ = T::deserialize(Introspect(&mut res));
+    res
+}
+
+/// Implements `SchemaFields`, `SchemaUpgrade` and `SchemaDowngrade` for two neighbour serde versions.
+///
+/// ```ignore
+/// def_fields!(UserV1 => UserV2 {
+///     add email = "";
+///     remove age = 0u32;
+///     rename name => full_name;
+/// });
+/// ```
+/// - `add` is a new field, given value is used when upgrading old data
+/// - `remove` is a removed field, given value is used when downgrading to the `UserV1`
+/// - `rename` changes name of field, value is left untouched
+///
+/// Both types should still be declared using `def_schema!` or `#[derive(Schema)]`, with `serde` argument.
+#[macro_export]
+macro_rules! def_fields {
+    ($prev:ty => $new:ty { $($ops:tt)* }) => {
+        impl $crate::fields::SchemaFields for $new {
+            type Prev = $prev;
+
+            #[allow(clippy::vec_init_then_push)]
+            fn fields() -> Result<Vec<$crate::fields::FieldOp>, $crate::schema::my_error::Error> {
+                #[allow(unused_mut)]
+                let mut ops = Vec::new();
+                $crate::def_fields!(@ops ops $($ops)*);
+                Ok(ops)
+            }
+        }
+
+        impl $crate::schema::SchemaUpgrade for $new {
+            type PrevVersion = $prev;
+            fn upgrade(val: $prev) -> Result<Self, $crate::schema::my_error::Error> {
+                $crate::fields::upgrade(val)
+            }
+        }
+
+        impl $crate::schema::SchemaDowngrade for $prev {
+            type NextVersion = $new;
+            fn downgrade(val: $new) -> Result<Self, $crate::schema::my_error::Error> {
+                $crate::fields::downgrade(val)
+            }
+        }
+    };
+
+    (@ops $ops:ident) => {};
+    (@ops $ops:ident add $name:ident = $default:expr; $($rest:tt)*) => {
+        $ops.push($crate::fields::FieldOp::Add {
+            name: stringify!($name),
+            default: $crate::fields::value($default)?,
+        });
+        $crate::def_fields!(@ops $ops $($rest)*);
+    };
+    (@ops $ops:ident remove $name:ident = $default:expr; $($rest:tt)*) => {
+        $ops.push($crate::fields::FieldOp::Remove {
+            name: stringify!($name),
+            default: $crate::fields::value($default)?,
+        });
+        $crate::def_fields!(@ops $ops $($rest)*);
+    };
+    (@ops $ops:ident rename $from:ident => $to:ident; $($rest:tt)*) => {
+        $ops.push($crate::fields::FieldOp::Rename {
+            from: stringify!($from),
+            to: stringify!($to),
+        });
+        $crate::def_fields!(@ops $ops $($rest)*);
+    };
+    (@ops $ops:ident $($arg:tt)*) => {
+        compile_error!(concat!(
+            "Unknown field operation while expanding def_fields: ",
+            stringify!($($arg)*)
+        ));
+    };
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[derive(Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+    struct UserV1 {
+        name: String,
+        age: u32,
+    }
+
+    def_schema!(UserV1 = 1; serde);
+
+    #[derive(Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+    struct UserV2 {
+        full_name: String,
+        email: String,
+    }
+
+    def_schema!(UserV2 = [2]; serde);
+
+    def_fields!(UserV1 => UserV2 {
+        rename name => full_name;
+        remove age = 0u32;
+        add email = "none";
+    });
+
+    #[test]
+    fn test_upgrade() {
+        let old = UserV1 {
+            name: "user".to_string(),
+            age: 42,
+        };
+        let new: UserV2 = load(1, old.save().unwrap()).unwrap();
+        assert_eq!(
+            new,
+            UserV2 {
+                full_name: "user".to_string(),
+                email: "none".to_string()
+            }
+        );
+    }
+
+    #[test]
+    fn test_downgrade() {
+        let new = UserV2 {
+            full_name: "user".to_string(),
+            email: "user@example.com".to_string(),
+        };
+        let old: UserV1 = load(2, new.save().unwrap()).unwrap();
+        assert_eq!(
+            old,
+            UserV1 {
+                name: "user".to_string(),
+                age: 0
+            }
+        );
+    }
+}
@@ -0,0 +1,205 @@
+//! Validators and triggers running inside of the write transaction, see `StorageOptions::hooks`.
+//!
+//! ```ignore
+//! let hooks = Hooks::default()
+//!     .validator("users/*", |event| match event.decode::<UserRecord>()? {
+//!         Some(user) if user.email.is_empty() => Err(err!("Email is required")),
+//!         _ => Ok(()),
+//!     })?
+//!     .trigger("users/*", |txn, event| {
+//!         let users: DataWrapperV1 = txn.children(&event.path.parent().unwrap().into())?.unwrap();
+//!         txn.put(&Path::parse("/stats/users"), users.children.len() as u64)
+//!     })?;
+//! ```
+
+use std::fmt;
+use std::sync::Arc;
+
+use crate::*;
+
+use super::errors::Trace;
+use super::{load_measured, Loaded, Metrics, ResultExt, WriteContext};
+use path::glob::Glob;
+
+/// Triggers may write to pathes with other triggers, but not deeper than this
+const MAX_DEPTH: usize = 16;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WriteOp {
+    Put,
+    Del,
+}
+
+/// Write passed to validators and triggers
+#[derive(Clone)]
+pub struct WriteEvent<'a> {
+    pub op: WriteOp,
+    pub path: Path,
+    /// Schema version of the new value, None for `Del`
+    pub version: Option<u64>,
+    /// Serialized new value, None for `Del`
+    pub value: Option<rmpv::Value>,
+    pub(crate) metrics: &'a Metrics,
+}
+
+impl<'a> WriteEvent<'a> {
+    /// Loads the new value as `T`, upgrading or downgrading it if needed
+    pub fn decode<T: Schema>(&self) -> Result<Option<T>, Trace> {
+        match (self.version, &self.value) {
+            (Some(version), Some(value)) => {
+                let res = load_measured::<T>(version, value.clone(), self.metrics)
+                    .epos(pos!(quiet self.path))?;
+                Ok(Some(res))
+            }
+            _ => Ok(None),
+        }
+    }
+}
+
+impl<'a> fmt::Debug for WriteEvent<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("WriteEvent")
+            .field("op", &self.op)
+            .field("path", &self.path)
+            .field("version", &self.version)
+            .field("value", &self.value)
+            .finish()
+    }
+}
+
+type Validator = dyn Fn(&WriteEvent) -> Result<(), Trace> + Send + Sync;
+type Trigger = dyn Fn(&mut HookTxn, &WriteEvent) -> Result<(), Error> + Send + Sync;
+
+/// Hooks registered for glob patterns, see `path::glob` for the syntax.
+///
+/// Validators run before the write and reject it with `Error::Rejected`.
+/// Triggers run after the write and may do additional writes, which run hooks too.
+/// Error in any of them aborts the whole transaction.
+///
+/// Hooks do not run for `Storage::migrate` and `StorageOptions::write_back`, they only change versions.
+#[derive(Clone, Default)]
+pub struct Hooks {
+    validators: Vec<(Glob, Arc<Validator>)>,
+    triggers: Vec<(Glob, Arc<Trigger>)>,
+}
+
+impl Hooks {
+    pub fn validator<F>(mut self, pattern: &str, f: F) -> Result<Self, Trace>
+    where
+        F: Fn(&WriteEvent) -> Result<(), Trace> + Send + Sync + 'static,
+    {
+        let glob = Glob::parse(pattern).epos(pos!())?;
+        self.validators.push((glob, Arc::new(f)));
+        Ok(self)
+    }
+
+    pub fn trigger<F>(mut self, pattern: &str, f: F) -> Result<Self, Trace>
+    where
+        F: Fn(&mut HookTxn, &WriteEvent) -> Result<(), Error> + Send + Sync + 'static,
+    {
+        let glob = Glob::parse(pattern).epos(pos!())?;
+        self.triggers.push((glob, Arc::new(f)));
+        Ok(self)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.validators.is_empty() && self.triggers.is_empty()
+    }
+}
+
+impl fmt::Debug for Hooks {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Hooks")
+            .field("validators", &self.validators.len())
+            .field("triggers", &self.triggers.len())
+            .finish()
+    }
+}
+
+/// Part of the write transaction available to triggers
+pub struct HookTxn<'a, 'env> {
+    txn: &'a mut lmdb::RwTransaction<'env>,
+    db: lmdb::Database,
+    ctx: WriteContext<'a>,
+    bytes: usize,
+}
+
+impl<'a, 'env> HookTxn<'a, 'env> {
+    /// Returns information about specified node if exists, the same as `Storage::children`
+    pub fn children<T: DataWrapper>(&self, path: &Path) -> Result<Option<T>, Error> {
+        RoTransactionExt::info(&*self.txn, self.db, path).trace_pos(pos!())
+    }
+
+    /// Same as `Storage::get`, older versions are written back if `StorageOptions::write_back` is set
+    pub fn get<T: Schema>(&mut self, path: &Path) -> Result<Option<T>, Error> {
+        if !self.ctx.options.write_back {
+            return RoTransactionExt::get(&*self.txn, self.db, path, self.ctx.metrics)
+                .trace_pos(pos!());
+        }
+        let loaded: Option<Loaded<T>> =
+            RwTransactionExt::get_write_back(self.txn, self.db, path, self.ctx.metrics)
+                .trace_pos(pos!())?;
+        Ok(loaded.map(|loaded| {
+            self.bytes += loaded.written;
+            loaded.value
+        }))
+    }
+
+    pub fn put<T: Schema>(&mut self, path: &Path, val: T) -> Result<(), Error> {
+        self.bytes += RwTransactionExt::put(self.txn, self.db, path, val, &self.ctx)
+            .trace_pos(pos!())?;
+        Ok(())
+    }
+
+    pub fn del(&mut self, path: &Path) -> Result<(), Error> {
+        self.bytes += RwTransactionExt::del(self.txn, self.db, path, &self.ctx).trace_pos(pos!())?;
+        Ok(())
+    }
+}
+
+/// Runs all validators matching the path of event
+pub(crate) fn validate(ctx: &WriteContext, event: &WriteEvent) -> Result<(), Error> {
+    for (glob, validator) in &ctx.options.hooks.validators {
+        if glob.matches(&event.path) {
+            validator(event).map_err(|trace| Error::Rejected {
+                path: event.path.clone(),
+                trace,
+            })?;
+        }
+    }
+    Ok(())
+}
+
+/// Runs all triggers matching the path of event. Returns number of bytes they have written.
+pub(crate) fn trigger(
+    txn: &mut lmdb::RwTransaction,
+    db: lmdb::Database,
+    ctx: &WriteContext,
+    event: &WriteEvent,
+) -> Result<usize, Error> {
+    let mut hook_txn = HookTxn {
+        txn,
+        db,
+        ctx: WriteContext {
+            log: ctx.log.clone(),
+            options: ctx.options,
+            index_db: ctx.index_db,
+            depth: ctx.depth + 1,
+            metrics: ctx.metrics,
+        },
+        bytes: 0,
+    };
+    for (glob, trigger) in &ctx.options.hooks.triggers {
+        if !glob.matches(&event.path) {
+            continue;
+        }
+        if hook_txn.ctx.depth > MAX_DEPTH {
+            return Err(Error::Other {
+                path: Some(event.path.clone()),
+                trace: err!("Triggers are nested deeper than {}", MAX_DEPTH),
+            });
+        }
+        trigger(&mut hook_txn, event).trace_pos(pos!())?;
+    }
+    Ok(hook_txn.bytes)
+}
@@ -0,0 +1,305 @@
+//! Secondary indexes over values of schema families, see `StorageOptions::indexes`.
+//!
+//! ```ignore
+//! let options = StorageOptions {
+//!     indexes: vec![Index::new::<UserRecord, _>("email", "users/*", |user| {
+//!         vec![user.email.to_lowercase()]
+//!     })?],
+//!     ..Default::default()
+//! };
+//! let storage = Storage::connect_with(path, options)?;
+//! let found: Vec<Path> = storage.lookup("email", "user@example.com")?;
+//! ```
+
+use std::collections::BTreeSet;
+use std::fmt;
+use std::sync::Arc;
+
+use lmdb::Cursor;
+use lmdb_sys as ffi;
+
+use crate::*;
+
+use super::errors::Trace;
+use super::{load_node, query, LmdbResultExt, Metrics, ResultExt, WriteContext};
+use path::glob::Glob;
+use schema::chain::{describe, Chain};
+
+/// Name of LMDB database with entries of all indexes
+pub(crate) const DB_NAME: &str = "lmtreedb_index";
+
+type Extractor = dyn Fn(&Path, DataWrapperV1, &Metrics) -> Result<Vec<String>, Error> + Send + Sync;
+
+/// Index of values at pathes matching the glob pattern by keys extracted from them.
+///
+/// Entries are kept in a separate LMDB database and updated in the same transaction as `put` and `del`.
+/// Values of other schema families are not indexed, use `constraints` to forbid them.
+/// Stored values which cannot be loaded as the family of index are skipped with a warning.
+///
+/// Indexes are not updated when `StorageOptions::indexes` do not contain them,
+/// so `Storage::reindex` should be called after adding an index to the existing database.
+#[derive(Clone)]
+pub struct Index {
+    name: String,
+    glob: Glob,
+    family: Chain,
+    extract: Arc<Extractor>,
+}
+
+impl Index {
+    /// Indexes values of family `F` at `pattern` by keys returned from `extract`.
+    /// See `path::glob` for the pattern syntax.
+    pub fn new<F, E>(name: &str, pattern: &str, extract: E) -> Result<Index, Trace>
+    where
+        F: Schema,
+        E: Fn(&F) -> Vec<String> + Send + Sync + 'static,
+    {
+        if name.is_empty() || name.contains('\0') {
+            return Err(err!("Invalid index name {:?}", name));
+        }
+        let extract = move |path: &Path, info: DataWrapperV1, metrics: &Metrics| {
+            let value = load_node::<F>(path, info, metrics).trace_pos(pos!())?;
+            Ok(extract(&value))
+        };
+        Ok(Index {
+            name: name.to_string(),
+            glob: Glob::parse(pattern).epos(pos!())?,
+            family: describe::<F>(),
+            extract: Arc::new(extract),
+        })
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn entry_key(&self, key: &str) -> Vec<u8> {
+        entry_key(&self.name, key)
+    }
+}
+
+impl fmt::Debug for Index {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Index")
+            .field("name", &self.name)
+            .field("glob", &self.glob)
+            .finish()
+    }
+}
+
+/// Key of LMDB entry, the same key has all pathes as duplicate values
+fn entry_key(index: &str, key: &str) -> Vec<u8> {
+    let mut res = Vec::with_capacity(index.len() + key.len() + 1);
+    res.extend_from_slice(index.as_bytes());
+    res.push(0);
+    res.extend_from_slice(key.as_bytes());
+    res
+}
+
+/// Pathes are stored by parts, because names like `..` cannot be parsed back from the key
+fn encode_path(path: &Path) -> Result<Vec<u8>, Error> {
+    let parts = path.components().map(rmpv::Value::from).collect();
+    let mut res = Vec::new();
+    rmpv::encode::write_value(&mut res, &rmpv::Value::Array(parts)).epos(pos!())?;
+    Ok(res)
+}
+
+fn decode_path(mut data: &[u8]) -> Result<Path, Trace> {
+    let value = rmpv::decode::read_value(&mut data).epos(pos!())?;
+    let parts = match value {
+        rmpv::Value::Array(parts) => parts,
+        other => return Err(err!("Expected array of path parts, got {}", other)),
+    };
+    let parts: Option<Vec<&str>> = parts.iter().map(|x| x.as_str()).collect();
+    Ok(Path::from_parts(parts.err(pos!())?))
+}
+
+/// Value of the node passed to `update`
+#[derive(Clone, Copy)]
+pub(crate) struct Node<'a> {
+    /// Known only for the values being written
+    type_name: Option<&'static str>,
+    version: u64,
+    data: &'a rmpv::Value,
+}
+
+impl<'a> Node<'a> {
+    pub fn stored(info: &'a DataWrapperV1) -> Self {
+        Node {
+            type_name: None,
+            version: info.version,
+            data: &info.data,
+        }
+    }
+
+    pub fn new<T: Schema>(data: &'a rmpv::Value) -> Self {
+        Node {
+            type_name: Some(std::intrinsics::type_name::<T>()),
+            version: T::version(),
+            data,
+        }
+    }
+}
+
+/// Keys of `index` for the node, empty if there is no node or it belongs to another family
+fn keys(
+    index: &Index,
+    path: &Path,
+    node: Option<Node>,
+    metrics: &Metrics,
+) -> Result<BTreeSet<String>, Error> {
+    let node = match node {
+        None => return Ok(BTreeSet::new()),
+        Some(node) => node,
+    };
+    if let Some(type_name) = node.type_name {
+        match index.family.get(node.version) {
+            Some(x) if x.type_name == type_name => {}
+            _ => return Ok(BTreeSet::new()),
+        }
+    }
+    let info = DataWrapperV1 {
+        children: Default::default(),
+        version: node.version,
+        data: node.data.clone(),
+    };
+    let res = (index.extract)(path, info, metrics).trace_pos(pos!(quiet index.name))?;
+    Ok(res.into_iter().collect())
+}
+
+/// Updates all indexes covering `path` after replacing `old` value with `new` one.
+pub(crate) fn update(
+    txn: &mut lmdb::RwTransaction,
+    ctx: &WriteContext,
+    path: &Path,
+    old: Option<Node>,
+    new: Option<Node>,
+) -> Result<(), Error> {
+    let db = match ctx.index_db {
+        None => return Ok(()),
+        Some(db) => db,
+    };
+    let mut encoded = None;
+    for index in &ctx.options.indexes {
+        if !index.glob.matches(path) {
+            continue;
+        }
+        // Type of stored value is unknown, so it may be a value of another family
+        let old_keys = keys(index, path, old, ctx.metrics).unwrap_or_else(|e| {
+            slog::warn!(ctx.log, "cannot extract index keys of the old value";
+                "index" => &index.name, "path" => %path, "error" => %e);
+            BTreeSet::new()
+        });
+        let new_keys = keys(index, path, new, ctx.metrics).trace_pos(pos!())?;
+        if old_keys == new_keys {
+            continue;
+        }
+
+        if encoded.is_none() {
+            encoded = Some(encode_path(path).trace_pos(pos!())?);
+        }
+        let value = encoded.as_ref().unwrap();
+        for key in old_keys.difference(&new_keys) {
+            del_entry(txn, db, &index.entry_key(key), value).lmdb(Some(path), pos!())?;
+        }
+        for key in new_keys.difference(&old_keys) {
+            txn.put(db, &index.entry_key(key), value, lmdb::WriteFlags::empty())
+                .lmdb(Some(path), pos!())?;
+        }
+    }
+    Ok(())
+}
+
+/// Removes single path from the key, if it is there.
+///
+/// `RwTransaction::del` of lmdb passes dangling pointer to the data, so cursor is used instead.
+fn del_entry(
+    txn: &mut lmdb::RwTransaction,
+    db: lmdb::Database,
+    key: &[u8],
+    data: &[u8],
+) -> Result<(), lmdb::Error> {
+    let mut cursor = txn.open_rw_cursor(db)?;
+    match cursor.get(Some(key), Some(data), ffi::MDB_GET_BOTH) {
+        Err(lmdb::Error::NotFound) => Ok(()),
+        Err(e) => Err(e),
+        Ok(_) => cursor.del(lmdb::WriteFlags::empty()),
+    }
+}
+
+impl Storage {
+    /// Returns pathes of all nodes which have `key` in the index `index`, sorted
+    pub fn lookup(&self, index: &str, key: &str) -> Result<Vec<Path>, Error> {
+        self.index(index).trace_pos(pos!())?;
+        let db = self.index_db.err(pos!())?;
+        let ro = self.env.begin_ro_txn().lmdb(None, pos!())?;
+        let mut cursor = ro.open_ro_cursor(db).lmdb(None, pos!())?;
+        let iter = match cursor.iter_dup_of(&entry_key(index, key)) {
+            Err(lmdb::Error::NotFound) => return Ok(Vec::new()),
+            res => res.lmdb(None, pos!())?,
+        };
+        let mut res = Vec::new();
+        for (_, data) in iter {
+            res.push(decode_path(data).epos(pos!(quiet (index, key)))?);
+        }
+        res.sort();
+        Ok(res)
+    }
+
+    /// Drops all entries of the index and builds it again from the existing nodes.
+    /// Returns number of indexed nodes.
+    pub fn reindex(&self, index: &str) -> Result<usize, Error> {
+        let index = self.index(index).trace_pos(pos!())?;
+        let db = self.index_db.err(pos!())?;
+        let mut rw = self.env.begin_rw_txn().lmdb(None, pos!())?;
+
+        let prefix = entry_key(&index.name, "");
+        let stale: Vec<(Vec<u8>, Vec<u8>)> = {
+            // `iter_from` of lmdb panics when there is nothing after the key, so all entries are scanned
+            let mut cursor = rw.open_ro_cursor(db).lmdb(None, pos!())?;
+            cursor
+                .iter()
+                .filter(|(key, _)| key.starts_with(&prefix))
+                .map(|(key, data)| (key.to_vec(), data.to_vec()))
+                .collect()
+        };
+        for (key, data) in stale {
+            del_entry(&mut rw, db, &key, &data).lmdb(None, pos!())?;
+        }
+
+        let mut count = 0;
+        for path in query::glob_impl(&rw, self.db, &index.glob).trace_pos(pos!())? {
+            let info: DataWrapperV1 =
+                RoTransactionExt::info(&rw, self.db, &path).trace_pos(pos!())?.err(pos!())?;
+            let keys = match keys(index, &path, Some(Node::stored(&info)), &self.metrics) {
+                Ok(keys) => keys,
+                Err(e) => {
+                    slog::warn!(self.logger(), "cannot extract index keys";
+                        "index" => &index.name, "path" => %path, "error" => %e);
+                    continue;
+                }
+            };
+            if keys.is_empty() {
+                continue;
+            }
+            let value = encode_path(&path).trace_pos(pos!())?;
+            for key in keys {
+                rw.put(db, &index.entry_key(&key), &value, lmdb::WriteFlags::empty())
+                    .lmdb(Some(&path), pos!())?;
+            }
+            count += 1;
+        }
+        rw.commit().lmdb(None, pos!())?;
+        Ok(count)
+    }
+
+    fn index(&self, name: &str) -> Result<&Index, Error> {
+        match self.options.indexes.iter().find(|x| x.name == name) {
+            Some(index) => Ok(index),
+            None => Err(Error::Other {
+                path: None,
+                trace: err!("There is no index '{}'", name),
+            }),
+        }
+    }
+}
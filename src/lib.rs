@@ -1,10 +1,11 @@
 #![feature(never_type)]
 #![feature(core_intrinsics)]
 
-#[macro_use]
-extern crate slog_scope;
+// Allows to use `#[derive(Schema)]` inside this crate
+extern crate self as lmtreedb;
 
 use std::cmp::Ordering;
+use std::time::Instant;
 
 use lmdb::Transaction;
 
@@ -12,17 +13,72 @@ use schema::*;
 use wrappers::VersionWrapper;
 use wrappers::{DataWrapper, DataWrapperV1};
 
-use path::{Path, PathPart, Root};
+use errors::{LmdbResultExt, ResultExt, TraceResultExt};
+use metrics::{Metrics, Op};
+use path::{Path, PathPart, Root, TypedPath};
 use my_error::*;
 
+pub use errors::Error;
+
+mod collections;
+pub mod constraints;
+mod errors;
+pub mod fields;
+pub mod hooks;
+pub mod index;
+pub mod metrics;
+pub mod migrate;
+pub mod nested;
 pub mod path;
+mod query;
 pub mod schema;
+pub mod scope;
+pub mod stats;
 pub mod wrappers;
 
 #[derive(Debug)]
 pub struct Storage {
     db: lmdb::Database,
+    /// Entries of `StorageOptions::indexes`, opened only if there are any
+    index_db: Option<lmdb::Database>,
     env: lmdb::Environment,
+    options: StorageOptions,
+    metrics: Metrics,
+}
+
+/// Optional behaviour of the `Storage`. Use `StorageOptions::default()` to get plain storage.
+#[derive(Clone, Debug, Default)]
+pub struct StorageOptions {
+    /// When `Storage::get` finds version older than requested, upgraded value is saved back.
+    ///
+    /// So hot nodes are migrated gradually and stop paying for upgrades on every read.
+    /// Reads of triggers by `HookTxn::get` are written back too.
+    pub write_back: bool,
+
+    /// Logger for all operations. Global `slog_scope` logger is used if it is not set.
+    ///
+    /// Each operation emits debug record with `op`, `path`, schema `version`, `bytes` written and `duration_us`.
+    pub logger: Option<slog::Logger>,
+
+    /// Every `put` checks that the value matches all constraints covering its path.
+    /// Violations are reported as `Error::TypeMismatch`.
+    pub constraints: Vec<constraints::Constraint>,
+
+    /// Validators and triggers running inside of `put` and `del` transactions
+    pub hooks: hooks::Hooks,
+
+    /// Secondary indexes updated by `put` and `del`, see `Storage::lookup`
+    pub indexes: Vec<index::Index>,
+}
+
+/// Everything `RwTransactionExt` needs to know about the `Storage`
+struct WriteContext<'a> {
+    log: slog::Logger,
+    options: &'a StorageOptions,
+    index_db: Option<lmdb::Database>,
+    /// Number of triggers this write is nested in
+    depth: usize,
+    metrics: &'a Metrics,
 }
 
 /// Deserializes val to required type
@@ -30,7 +86,7 @@ pub struct Storage {
 /// This function does all required upgrades or downgrades to convert given version to the required.
 ///
 /// * version argument is the version of saved data. Not the returned version.
-fn load<T: Schema>(version: u64, val: rmpv::Value) -> Result<T, Error> {
+fn load<T: Schema>(version: u64, val: rmpv::Value) -> Result<T, my_error::Error> {
     match version.cmp(&T::version()) {
         Ordering::Equal => {
             // Just load it
@@ -51,6 +107,13 @@ fn load<T: Schema>(version: u64, val: rmpv::Value) -> Result<T, Error> {
                 ));
             }
 
+            // Try to skip intermediate versions first
+            if let Some(shortcut) = T::shortcut(version) {
+                let res = shortcut(val)
+                    .epos(pos!(quiet("shortcut", version, std::intrinsics::type_name::<T>())))?;
+                return Ok(res);
+            }
+
             // Load version-1
             let down = load::<T::PrevVersion>(version, val)
                 .epos(pos!(quiet("upgrade", std::intrinsics::type_name::<T>())))?;
@@ -80,38 +143,134 @@ fn load<T: Schema>(version: u64, val: rmpv::Value) -> Result<T, Error> {
     }
 }
 
+/// Loads data of the node at `path` as `T`.
+/// All loads of stored values go through here, so conversions are recorded to `metrics`.
+///
+/// Failures are reported as `Error::VersionChain` if stored version does not belong to the family of `T`,
+/// or as `Error::Decode` otherwise.
+fn load_node<T: Schema>(path: &Path, info: DataWrapperV1, metrics: &Metrics) -> Result<T, Error> {
+    let version = info.version;
+    match load_measured(version, info.data, metrics) {
+        Ok(res) => Ok(res),
+        Err(trace) if schema::chain::describe::<T>().get(version).is_none() => {
+            Err(Error::VersionChain {
+                path: path.clone(),
+                stored: version,
+                requested: T::version(),
+                trace,
+            })
+        }
+        Err(trace) => Err(Error::Decode {
+            path: path.clone(),
+            trace,
+        }),
+    }
+}
+
+/// Same as `load`, but records conversions to `metrics` if it succeeds
+fn load_measured<T: Schema>(
+    version: u64,
+    val: rmpv::Value,
+    metrics: &Metrics,
+) -> Result<T, my_error::Error> {
+    let start = Instant::now();
+    let res = load(version, val).epos(pos!())?;
+    metrics.loaded::<T>(version, start.elapsed());
+    Ok(res)
+}
+
+/// Value loaded from the database with some info about stored data
+struct Loaded<T> {
+    value: T,
+    /// Stored version, before any conversions
+    version: u64,
+    /// Bytes written while loading, by write-back
+    written: usize,
+}
+
 /// Implementations of all read-only actions based on lmdb::Transaction
 trait RoTransactionExt: lmdb::Transaction {
     /// Loads DataWrapper for specified path if exists.
     /// DataWrapper contains all *info* about specified object (and serialized data)
     fn info<T: DataWrapper>(&self, db: lmdb::Database, path: &Path) -> Result<Option<T>, Error> {
-        let key = path.to_string();
+        let res = RoTransactionExt::info_sized(self, db, path).trace_pos(pos!())?;
+        Ok(res.map(|(info, _)| info))
+    }
+
+    /// Same as `info`, but also returns size of stored node
+    fn info_sized<T: DataWrapper>(
+        &self,
+        db: lmdb::Database,
+        path: &Path,
+    ) -> Result<Option<(T, usize)>, Error> {
+        let key = path.key();
 
         let res = lmdb::Transaction::get(self, db, &key);
         if let Err(lmdb::Error::NotFound) = res {
             return Ok(None);
         }
 
-        let mut data = res?;
-        let parsed = rmpv::decode::read_value(&mut data).epos(pos!())?;
+        let mut data = res.lmdb(Some(path), pos!())?;
+        let bytes = data.len();
+        let parsed = rmpv::decode::read_value(&mut data).epos(pos!()).decode(path)?;
 
-        let loaded = load::<VersionWrapper<T>>(1, parsed).epos(pos!(path))?;
-        Ok(Some(loaded.data))
+        let loaded = load::<VersionWrapper<T>>(1, parsed).epos(pos!(path)).decode(path)?;
+        Ok(Some((loaded.data, bytes)))
     }
 
     /// Deserializes and returns object from database if exists.
-    fn get<T: Schema>(&self, db: lmdb::Database, path: &Path) -> Result<Option<T>, Error> {
-        let data: Option<DataWrapperV1> = RoTransactionExt::info(self, db, path).epos(pos!())?;
+    fn get<T: Schema>(
+        &self,
+        db: lmdb::Database,
+        path: &Path,
+        metrics: &Metrics,
+    ) -> Result<Option<T>, Error> {
+        let res = RoTransactionExt::get_loaded(self, db, path, metrics).trace_pos(pos!())?;
+        Ok(res.map(|x| x.value))
+    }
 
-        let data = match data {
-            None => return Ok(None),
-            Some(val) => val,
-        };
+    /// Same as `get`, but also returns info about stored data
+    fn get_loaded<T: Schema>(
+        &self,
+        db: lmdb::Database,
+        path: &Path,
+        metrics: &Metrics,
+    ) -> Result<Option<Loaded<T>>, Error> {
+        let (data, bytes): (DataWrapperV1, usize) =
+            match RoTransactionExt::info_sized(self, db, path).trace_pos(pos!())? {
+                None => return Ok(None),
+                Some(val) => val,
+            };
+        metrics.read(bytes);
 
         let version = data.version;
-        let data = data.data;
-        let value = load(version, data).epos(pos!(T::version(), version))?;
-        Ok(Some(value))
+        let value = load_node(path, data, metrics).trace_pos(pos!())?;
+        Ok(Some(Loaded {
+            value,
+            version,
+            written: 0,
+        }))
+    }
+
+    /// Returns specified path and all its descendants. Parents always go before their children.
+    ///
+    /// Returns empty vec if path does not exist.
+    fn subtree(&self, db: lmdb::Database, path: &Path) -> Result<Vec<Path>, Error> {
+        let mut res = Vec::new();
+        let mut queue = vec![path.clone()];
+        while let Some(current) = queue.pop() {
+            let info: Option<DataWrapperV1> =
+                RoTransactionExt::info(self, db, &current).trace_pos(pos!(current))?;
+            if let Some(info) = info {
+                for child in info.children {
+                    let mut path = current.clone();
+                    path.push(&child);
+                    queue.push(path);
+                }
+                res.push(current);
+            }
+        }
+        Ok(res)
     }
 }
 
@@ -121,7 +280,15 @@ impl<T> RoTransactionExt for T where T: lmdb::Transaction {}
 trait RwTransactionExt {
     /// Same as put_unsafe, but also checks for path correctness
     /// and handles all stuff about children and parents
-    fn put<T: Schema>(&mut self, db: lmdb::Database, path: &Path, data: T) -> Result<(), Error>;
+    ///
+    /// All write methods return number of bytes written.
+    fn put<T: Schema>(
+        &mut self,
+        db: lmdb::Database,
+        path: &Path,
+        data: T,
+        ctx: &WriteContext,
+    ) -> Result<usize, Error>;
 
     /// Just puts data into database. No version or parents, only given data.
     fn put_unsafe<T: Schema>(
@@ -129,25 +296,27 @@ trait RwTransactionExt {
         db: lmdb::Database,
         path: &Path,
         data: T,
-    ) -> Result<(), Error>;
+    ) -> Result<usize, Error>;
 
-    fn del(&mut self, db: lmdb::Database, path: &Path) -> Result<(), Error>;
+    fn del(
+        &mut self,
+        db: lmdb::Database,
+        path: &Path,
+        ctx: &WriteContext,
+    ) -> Result<usize, Error>;
 
-    /// Helper method that wraps data in DataWrapper first.
-    fn put_unsafe_wrapped<T: Schema>(
+    /// Same as `RoTransactionExt::get_loaded`, but if stored version is older than `T`,
+    /// upgraded value is written back in this transaction.
+    fn get_write_back<T: Schema>(
         &mut self,
         db: lmdb::Database,
         path: &Path,
-        data: T,
-    ) -> Result<(), Error> {
-        let data = DataWrapperV1 {
-            children: Default::default(),
-            version: T::version(),
-            data: data.save()?,
-        };
-        self.put_unsafe_version(db, path, data).epos(pos!())?;
-        Ok(())
-    }
+        metrics: &Metrics,
+    ) -> Result<Option<Loaded<T>>, Error>;
+
+    /// Creates empty root and adds it to the list of roots.
+    /// Roots are not values of users, so constraints, hooks and indexes are not applied to them.
+    fn put_root(&mut self, db: lmdb::Database, root: &Path) -> Result<usize, Error>;
 
     /// Wraps data in VersionWrapper that stores version of inner data.
     fn put_unsafe_version<T: DataWrapper>(
@@ -155,54 +324,107 @@ trait RwTransactionExt {
         db: lmdb::Database,
         path: &Path,
         data: T,
-    ) -> Result<(), Error> {
+    ) -> Result<usize, Error> {
         let data = VersionWrapper { data };
-        self.put_unsafe(db, path, data).epos(pos!())?;
-        Ok(())
+        let bytes = self.put_unsafe(db, path, data).trace_pos(pos!())?;
+        Ok(bytes)
     }
 }
 
 impl<'env> RwTransactionExt for lmdb::RwTransaction<'env> {
-    fn put<T: Schema>(&mut self, db: lmdb::Database, path: &Path, data: T) -> Result<(), Error> {
+    fn put<T: Schema>(
+        &mut self,
+        db: lmdb::Database,
+        path: &Path,
+        data: T,
+        ctx: &WriteContext,
+    ) -> Result<usize, Error> {
+        constraints::check::<T>(&ctx.options.constraints, path).trace_pos(pos!())?;
+        if !path.is_absolute() {
+            return Err(Error::Other {
+                path: Some(path.clone()),
+                trace: err!("'{}' is not inside of any root", path),
+            });
+        }
+
+        let value = data.save().epos(pos!())?;
+        // Value is cloned for hooks only if there are any
+        let event = if ctx.options.hooks.is_empty() {
+            None
+        } else {
+            Some(hooks::WriteEvent {
+                op: hooks::WriteOp::Put,
+                path: path.clone(),
+                version: Some(T::version()),
+                value: Some(value.clone()),
+                metrics: ctx.metrics,
+            })
+        };
+        if let Some(event) = &event {
+            hooks::validate(ctx, event).trace_pos(pos!())?;
+        }
+
         // First check is this path already used
         let existing: Option<DataWrapperV1> =
-            RoTransactionExt::info(self, db, path).epos(pos!())?;
+            RoTransactionExt::info(self, db, path).trace_pos(pos!())?;
+        let old = existing.as_ref().map(index::Node::stored);
+        index::update(self, ctx, path, old, Some(index::Node::new::<T>(&value))).trace_pos(pos!())?;
+        let mut bytes = 0;
         match existing {
             None => {
                 // It is new key, so tell parent abount new child first.
                 let (parent_path, name) = path.pop();
                 let name = name.err(pos!())?;
-                let parent: Option<DataWrapperV1> =
-                    RoTransactionExt::info(self, db, &parent_path).epos(pos!())?;
+                let mut parent: Option<DataWrapperV1> =
+                    RoTransactionExt::info(self, db, &parent_path).trace_pos(pos!())?;
+                if parent.is_none() && parent_path.len() == 1 {
+                    // Roots are created on demand
+                    bytes += self.put_root(db, &parent_path).trace_pos(pos!())?;
+                    parent = RoTransactionExt::info(self, db, &parent_path).trace_pos(pos!())?;
+                }
                 match parent {
-                    None => return Err(err!("No parent '{}' found for '{}'", parent_path, path)),
+                    None => {
+                        return Err(Error::ParentMissing {
+                            path: path.clone(),
+                            trace: err!("No parent '{}' found for '{}'", parent_path, path),
+                        })
+                    }
                     Some(mut par) => {
                         par.children.insert(name);
-                        self.put_unsafe_version(db, &parent_path, par).epos(pos!())?;
+                        bytes += self.put_unsafe_version(db, &parent_path, par).trace_pos(pos!())?;
                     }
                 }
                 // And now we can safely put it
-                self.put_unsafe_wrapped(db, path, data).epos(pos!())?;
+                let data = DataWrapperV1 {
+                    children: Default::default(),
+                    version: T::version(),
+                    data: value,
+                };
+                bytes += self.put_unsafe_version(db, path, data).trace_pos(pos!())?;
             }
             Some(ex) => {
                 // It exists. So parent already have link to this node and we can just overwrite it.
                 if T::version() < ex.version {
-                    warn!("overwriting newer version with older");
+                    slog::warn!(ctx.log, "overwriting newer version with older";
+                        "path" => %path, "stored" => ex.version, "version" => T::version());
                 }
-                self.put_unsafe_version(
+                bytes += self.put_unsafe_version(
                     db,
                     path,
                     DataWrapperV1 {
                         children: ex.children,
                         version: T::version(),
-                        data: data.save().epos(pos!())?,
+                        data: value,
                     },
                 )
-                .epos(pos!())?;
+                .trace_pos(pos!())?;
             }
         }
 
-        Ok(())
+        if let Some(event) = &event {
+            bytes += hooks::trigger(self, db, ctx, event).trace_pos(pos!())?;
+        }
+        Ok(bytes)
     }
 
     fn put_unsafe<T: Schema>(
@@ -210,29 +432,54 @@ impl<'env> RwTransactionExt for lmdb::RwTransaction<'env> {
         db: lmdb::Database,
         path: &Path,
         data: T,
-    ) -> Result<(), Error> {
+    ) -> Result<usize, Error> {
         let data = data.save()?;
         let mut vec = Vec::new();
         rmpv::encode::write_value(&mut vec, &data).epos(pos!())?;
-        self.put(db, &path.to_string(), &vec, lmdb::WriteFlags::NO_DUP_DATA)?;
-        Ok(())
+        self.put(db, &path.key(), &vec, lmdb::WriteFlags::NO_DUP_DATA)
+            .lmdb(Some(path), pos!())?;
+        Ok(vec.len())
     }
 
     /// Removes specified node and removes it from parent.
-    fn del(&mut self, db: lmdb::Database, path: &Path) -> Result<(), Error> {
+    fn del(
+        &mut self,
+        db: lmdb::Database,
+        path: &Path,
+        ctx: &WriteContext,
+    ) -> Result<usize, Error> {
         // First check that there is no any children
-        let info: DataWrapperV1 = RoTransactionExt::info(self, db, path)
-            .epos(pos!())?
-            .err(pos!())?;
+        let info: DataWrapperV1 = match RoTransactionExt::info(self, db, path).trace_pos(pos!())? {
+            None => {
+                return Err(Error::NotFound {
+                    path: path.clone(),
+                    trace: err!("Nothing to del"),
+                })
+            }
+            Some(info) => info,
+        };
         if !info.children.is_empty() {
-            return Err(err!("Cannot del file with children"));
+            return Err(Error::HasChildren {
+                path: path.clone(),
+                trace: err!("Cannot del file with children"),
+            });
         }
 
+        let event = hooks::WriteEvent {
+            op: hooks::WriteOp::Del,
+            path: path.clone(),
+            version: None,
+            value: None,
+            metrics: ctx.metrics,
+        };
+        hooks::validate(ctx, &event).trace_pos(pos!())?;
+        index::update(self, ctx, path, Some(index::Node::stored(&info)), None).trace_pos(pos!())?;
+
         // Then remove this node from it's parent.
         let (parent_path, name) = path.pop();
         let name = name.err(pos!())?;
         let mut parent: DataWrapperV1 = RoTransactionExt::info(self, db, &parent_path)
-            .epos(pos!())?
+            .trace_pos(pos!())?
             .err(pos!())?;
         let res = parent.children.remove(&name);
         if !res {
@@ -240,65 +487,299 @@ impl<'env> RwTransactionExt for lmdb::RwTransaction<'env> {
         }
 
         // Now put it.
-        self.put_unsafe_version(db, &parent_path, parent)
-            .epos(pos!())?;
-        self.del(db, &path.to_string(), None).epos(pos!())?;
-        Ok(())
+        let mut bytes = self.put_unsafe_version(db, &parent_path, parent)
+            .trace_pos(pos!())?;
+        self.del(db, &path.key(), None).lmdb(Some(path), pos!())?;
+        bytes += hooks::trigger(self, db, ctx, &event).trace_pos(pos!())?;
+        Ok(bytes)
+    }
+
+    fn get_write_back<T: Schema>(
+        &mut self,
+        db: lmdb::Database,
+        path: &Path,
+        metrics: &Metrics,
+    ) -> Result<Option<Loaded<T>>, Error> {
+        let loaded = RoTransactionExt::get_loaded(self, db, path, metrics).trace_pos(pos!())?;
+        let mut loaded: Loaded<T> = match loaded {
+            None => return Ok(None),
+            Some(loaded) => loaded,
+        };
+        if loaded.version >= T::version() {
+            // Never overwrite newer versions, because it may lose some data
+            return Ok(Some(loaded));
+        }
+
+        let children = RoTransactionExt::info::<DataWrapperV1>(self, db, path)
+            .trace_pos(pos!())?
+            .err(pos!())?
+            .children;
+        // Value is consumed by save(), so load it back from the raw data. It is cheap for the same version.
+        let data = loaded.value.save().epos(pos!())?;
+        loaded.written = self.put_unsafe_version(
+            db,
+            path,
+            DataWrapperV1 {
+                children,
+                version: T::version(),
+                data: data.clone(),
+            },
+        )
+        .trace_pos(pos!())?;
+        loaded.value = T::load(data).epos(pos!()).decode(path)?;
+        Ok(Some(loaded))
+    }
+
+    fn put_root(&mut self, db: lmdb::Database, root: &Path) -> Result<usize, Error> {
+        let (roots_path, name) = root.pop();
+        let name = name.err(pos!())?;
+        let roots: Option<DataWrapperV1> =
+            RoTransactionExt::info(self, db, &roots_path).trace_pos(pos!())?;
+        let mut roots = match roots {
+            None => {
+                return Err(Error::ParentMissing {
+                    path: root.clone(),
+                    trace: err!("No list of roots found for '{}'", root),
+                })
+            }
+            Some(roots) => roots,
+        };
+        roots.children.insert(name);
+        let mut bytes = self.put_unsafe_version(db, &roots_path, roots).trace_pos(pos!())?;
+
+        let data = DataWrapperV1 {
+            children: Default::default(),
+            version: <()>::version(),
+            data: ().save().epos(pos!())?,
+        };
+        bytes += self.put_unsafe_version(db, root, data).trace_pos(pos!())?;
+        Ok(bytes)
     }
 }
 
 impl Storage {
     /// Creates or loads database at the specified location.
     pub fn connect(path: &std::path::Path) -> Result<Self, Error> {
-        let env = lmdb::Environment::new().open(&path).epos(pos!())?;
-        let db = env.create_db(None, Default::default()).epos(pos!())?;
-        let mut res = Self { db, env };
+        Self::connect_with(path, StorageOptions::default())
+    }
+
+    /// Same as `connect`, but allows to specify non-default options.
+    pub fn connect_with(path: &std::path::Path, options: StorageOptions) -> Result<Self, Error> {
+        let env = lmdb::Environment::new()
+            .set_max_dbs(1)
+            .open(&path)
+            .lmdb(None, pos!())?;
+        let db = env.create_db(None, Default::default()).lmdb(None, pos!())?;
+        let index_db = if options.indexes.is_empty() {
+            None
+        } else {
+            let db = env.create_db(Some(index::DB_NAME), lmdb::DatabaseFlags::DUP_SORT);
+            Some(db.lmdb(None, pos!())?)
+        };
+        let mut res = Self {
+            db,
+            index_db,
+            env,
+            options,
+            metrics: Metrics::default(),
+        };
         res.init_root()?;
         Ok(res)
     }
 
-    /// Unsafely puts the root node if it does not exists  
+    /// Unsafely puts the list of roots and the default root if they do not exist
     fn init_root(&mut self) -> Result<(), Error> {
-        let mut rw = self.env.begin_rw_txn()?;
+        let mut rw = self.env.begin_rw_txn().lmdb(None, pos!())?;
+        let roots: Option<DataWrapperV1> =
+            RoTransactionExt::info(&rw, self.db, &Path::new()).trace_pos(pos!())?;
+        let root = Root::default().path();
         let existing: Option<()> =
-            RoTransactionExt::get(&rw, self.db, &Root::default().path()).epos(pos!())?;
+            RoTransactionExt::get(&rw, self.db, &root, &self.metrics).trace_pos(pos!())?;
+        if roots.is_some() && existing.is_some() {
+            return Ok(());
+        }
+
+        if roots.is_none() {
+            // Databases created before multiple roots have only the default one
+            let mut roots = DataWrapperV1 {
+                children: Default::default(),
+                version: <()>::version(),
+                data: ().save().epos(pos!())?,
+            };
+            if existing.is_some() {
+                roots.children.insert(root.key().to_string());
+            }
+            RwTransactionExt::put_unsafe_version(&mut rw, self.db, &Path::new(), roots)
+                .trace_pos(pos!())?;
+        }
         if existing.is_none() {
-            RwTransactionExt::put_unsafe_wrapped(&mut rw, self.db, &Root::default().path(), ())
-                .epos(pos!())?;
-            rw.commit()?;
+            RwTransactionExt::put_root(&mut rw, self.db, &root).trace_pos(pos!())?;
         }
+        rw.commit().lmdb(None, pos!())?;
         Ok(())
     }
 
+    /// Returns all roots existing in the database, sorted by name
+    pub fn roots(&self) -> Result<Vec<Root>, Error> {
+        let ro = self.env.begin_ro_txn().lmdb(None, pos!())?;
+        let info: Option<DataWrapperV1> =
+            RoTransactionExt::info(&ro, self.db, &Path::new()).trace_pos(pos!())?;
+        let mut names: Vec<String> = info
+            .map(|x| x.children.into_iter().collect())
+            .unwrap_or_default();
+        names.sort();
+        Ok(names.iter().map(|x| Root::named(x)).collect())
+    }
+
     /// Returns information about specified node if exists.
     pub fn children<T: DataWrapper>(&self, path: &Path) -> Result<Option<T>, Error> {
-        let ro = self.env.begin_ro_txn().epos(pos!())?;
-        let res = RoTransactionExt::info(&ro, self.db, path).epos(pos!())?;
+        let ro = self.env.begin_ro_txn().lmdb(None, pos!())?;
+        let res = RoTransactionExt::info(&ro, self.db, path).trace_pos(pos!())?;
         Ok(res)
     }
 
     /// Returns object at the specified path and deserializes it to the requires type.
     /// Returns error if deserialization failed
+    ///
+    /// If `StorageOptions::write_back` is set, older versions are upgraded and saved back.
     pub fn get<T: Schema>(&self, path: &Path) -> Result<Option<T>, Error> {
-        let ro = self.env.begin_ro_txn().epos(pos!())?;
-        let res = RoTransactionExt::get(&ro, self.db, path).epos(pos!())?;
+        let start = Instant::now();
+        let res = if self.options.write_back {
+            self.get_write_back(path)
+        } else {
+            self.get_ro(path)
+        };
+        let written = res.as_ref().map(|x| x.as_ref().map_or(0, |x| x.written));
+        self.finish_op(Op::Get, path, Some(T::version()), start, written);
+        res.map(|x| x.map(|x| x.value)).trace_pos(pos!())
+    }
+
+    fn get_ro<T: Schema>(&self, path: &Path) -> Result<Option<Loaded<T>>, Error> {
+        let ro = self.env.begin_ro_txn().lmdb(None, pos!())?;
+        let res =
+            RoTransactionExt::get_loaded(&ro, self.db, path, &self.metrics).trace_pos(pos!())?;
+        Ok(res)
+    }
+
+    fn get_write_back<T: Schema>(&self, path: &Path) -> Result<Option<Loaded<T>>, Error> {
+        {
+            // Most reads do not require any writes, so try to use read-only transaction first
+            let ro = self.env.begin_ro_txn().lmdb(None, pos!())?;
+            let info: Option<(DataWrapperV1, usize)> =
+                RoTransactionExt::info_sized(&ro, self.db, path).trace_pos(pos!())?;
+            match info {
+                None => return Ok(None),
+                Some((info, bytes)) if info.version >= T::version() => {
+                    self.metrics.read(bytes);
+                    let version = info.version;
+                    let value = load_node(path, info, &self.metrics).trace_pos(pos!())?;
+                    return Ok(Some(Loaded {
+                        value,
+                        version,
+                        written: 0,
+                    }));
+                }
+                Some(_) => {}
+            }
+        }
+
+        // Value is outdated. Check it again in the write transaction, because it may be changed already
+        let mut rw = self.env.begin_rw_txn().lmdb(None, pos!())?;
+        let res = RwTransactionExt::get_write_back(&mut rw, self.db, path, &self.metrics)
+            .trace_pos(pos!())?;
+        rw.commit().lmdb(None, pos!())?;
+        if res.as_ref().map_or(0, |x| x.written) > 0 {
+            slog::debug!(self.logger(), "write_back";
+                "op" => "write_back", "path" => %path, "version" => T::version());
+        }
         Ok(res)
     }
 
     /// Removes the specified node. Should not contain any children before removing.
     pub fn del(&self, path: &Path) -> Result<(), Error> {
-        let mut rw = self.env.begin_rw_txn().epos(pos!())?;
-        RwTransactionExt::del(&mut rw, self.db, path).epos(pos!())?;
-        rw.commit().epos(pos!())?;
-        Ok(())
+        let start = Instant::now();
+        let res = self.del_impl(path);
+        self.finish_op(Op::Del, path, None, start, res.as_ref().copied());
+        res.map(|_| ()).trace_pos(pos!())
+    }
+
+    fn del_impl(&self, path: &Path) -> Result<usize, Error> {
+        let mut rw = self.env.begin_rw_txn().lmdb(None, pos!())?;
+        let ctx = self.write_context();
+        let bytes = RwTransactionExt::del(&mut rw, self.db, path, &ctx).trace_pos(pos!())?;
+        rw.commit().lmdb(None, pos!())?;
+        Ok(bytes)
     }
 
     /// Put the data at the specified path. Parent must exists before adding new entry.
     pub fn put<T: Schema>(&self, path: &Path, val: T) -> Result<(), Error> {
-        let mut rw = self.env.begin_rw_txn().epos(pos!())?;
-        RwTransactionExt::put(&mut rw, self.db, path, val).epos(pos!())?;
-        rw.commit().epos(pos!())?;
-        Ok(())
+        let start = Instant::now();
+        let res = self.put_impl(path, val);
+        self.finish_op(Op::Put, path, Some(T::version()), start, res.as_ref().copied());
+        res.map(|_| ()).trace_pos(pos!())
+    }
+
+    fn put_impl<T: Schema>(&self, path: &Path, val: T) -> Result<usize, Error> {
+        let mut rw = self.env.begin_rw_txn().lmdb(None, pos!())?;
+        let ctx = self.write_context();
+        let bytes = RwTransactionExt::put(&mut rw, self.db, path, val, &ctx).trace_pos(pos!())?;
+        rw.commit().lmdb(None, pos!())?;
+        Ok(bytes)
+    }
+
+    /// Same as `get`, but the type of value is taken from the path part
+    pub fn get_typed<P: TypedPath>(&self, part: P) -> Result<Option<P::Value>, Error> {
+        self.get(&part.path()).trace_pos(pos!())
+    }
+
+    /// Same as `put`, but allows to put only the value of type bound to the path part
+    pub fn put_typed<P: TypedPath>(&self, part: P, val: P::Value) -> Result<(), Error> {
+        self.put(&part.path(), val).trace_pos(pos!())
+    }
+
+    /// Returns counters and latency histograms of all operations since `connect`
+    pub fn metrics(&self) -> metrics::Snapshot {
+        self.metrics.snapshot()
+    }
+
+    /// Logger from `StorageOptions::logger` or the global one
+    fn logger(&self) -> slog::Logger {
+        self.options.logger.clone().unwrap_or_else(slog_scope::logger)
+    }
+
+    fn write_context(&self) -> WriteContext<'_> {
+        WriteContext {
+            log: self.logger(),
+            options: &self.options,
+            index_db: self.index_db,
+            depth: 0,
+            metrics: &self.metrics,
+        }
+    }
+
+    /// Emits structured record about finished operation and updates metrics.
+    /// `res` contains number of written bytes.
+    fn finish_op(
+        &self,
+        op: Op,
+        path: &Path,
+        version: Option<u64>,
+        start: Instant,
+        res: Result<usize, &Error>,
+    ) {
+        let elapsed = start.elapsed();
+        self.metrics.op(op, elapsed, res);
+
+        let duration = elapsed.as_micros() as u64;
+        let op = op.name();
+        match res {
+            Ok(bytes) => slog::debug!(self.logger(), "{} {}", op, path;
+                "op" => op, "path" => %path, "version" => version,
+                "bytes" => bytes, "duration_us" => duration),
+            Err(e) => slog::debug!(self.logger(), "{} {} failed", op, path;
+                "op" => op, "path" => %path, "version" => version,
+                "duration_us" => duration, "error" => %e),
+        }
     }
 
     /// Closes and consumes the database.
@@ -308,19 +789,24 @@ impl Storage {
     }
 
     pub fn flush(&self) -> Result<(), Error> {
-        self.env.sync(true).epos(pos!())?;
+        self.env.sync(true).lmdb(None, pos!())?;
         Ok(())
     }
 }
 
 #[cfg(test)]
 mod test {
+    use std::fmt::Debug;
+
     use path::*;
+    use serde::de::DeserializeOwned;
+    use serde::Serialize;
 
     use super::*;
+    use my_error::Error;
     use rmpv::Value;
 
-    #[derive(Debug)]
+    #[derive(Clone, Debug)]
     struct Test1 {
         data: i64,
     }
@@ -347,7 +833,7 @@ mod test {
         }
     }
 
-    #[derive(Debug)]
+    #[derive(Clone, Debug)]
     struct Test2 {
         data: f64,
     }
@@ -375,6 +861,139 @@ mod test {
         }
     }
 
+    #[derive(Debug, serde::Serialize, serde::Deserialize)]
+    struct Short1 {
+        data: i64,
+    }
+
+    def_schema!(Short1 = 1; serde);
+
+    impl SchemaDowngrade for Short1 {
+        type NextVersion = Short2;
+        fn downgrade(val: Self::NextVersion) -> Result<Self, Error> {
+            Ok(Self { data: val.data })
+        }
+    }
+
+    #[derive(Debug, serde::Serialize, serde::Deserialize)]
+    struct Short2 {
+        data: i64,
+    }
+
+    def_schema!(Short2 = 2; serde);
+
+    impl SchemaUpgrade for Short2 {
+        type PrevVersion = Short1;
+        fn upgrade(val: Self::PrevVersion) -> Result<Self, Error> {
+            Ok(Self { data: val.data + 1 })
+        }
+    }
+
+    impl SchemaDowngrade for Short2 {
+        type NextVersion = Short3;
+        fn downgrade(val: Self::NextVersion) -> Result<Self, Error> {
+            Ok(Self { data: val.data })
+        }
+    }
+
+    #[derive(Debug, serde::Serialize, serde::Deserialize)]
+    struct Short3 {
+        data: i64,
+    }
+
+    def_schema!(Short3 = [3]; serde, shortcut(Short1));
+
+    impl SchemaUpgrade for Short3 {
+        type PrevVersion = Short2;
+        fn upgrade(val: Self::PrevVersion) -> Result<Self, Error> {
+            Ok(Self { data: val.data + 10 })
+        }
+    }
+
+    impl SchemaUpgradeFrom<Short1> for Short3 {
+        fn upgrade_from(val: Short1) -> Result<Self, Error> {
+            Ok(Self {
+                data: val.data + 100,
+            })
+        }
+    }
+
+    #[derive(Debug, serde::Serialize, serde::Deserialize, Schema)]
+    #[schema(version = 1, next = Derived2, serde)]
+    struct Derived1 {
+        data: i64,
+    }
+
+    impl From<Derived2> for Derived1 {
+        fn from(val: Derived2) -> Self {
+            Self { data: val.data as i64 }
+        }
+    }
+
+    #[derive(Debug, serde::Serialize, serde::Deserialize, Schema)]
+    #[schema(version = 2, prev = Derived1, serde)]
+    #[schema(last)]
+    struct Derived2 {
+        data: f64,
+    }
+
+    impl From<Derived1> for Derived2 {
+        fn from(val: Derived1) -> Self {
+            Self {
+                data: val.data as f64,
+            }
+        }
+    }
+
+    #[derive(Debug, serde::Serialize, serde::Deserialize, Schema)]
+    #[schema(version = 1, next = Generic2<T>, serde)]
+    #[serde(bound = "T: Serialize + DeserializeOwned")]
+    struct Generic1<T: Debug + Serialize + DeserializeOwned> {
+        data: T,
+    }
+
+    impl<T: Debug + Serialize + DeserializeOwned> From<Generic2<T>> for Generic1<T> {
+        fn from(val: Generic2<T>) -> Self {
+            Self {
+                data: val.items.into_iter().next().unwrap(),
+            }
+        }
+    }
+
+    #[derive(Debug, serde::Serialize, serde::Deserialize, Schema)]
+    #[schema(version = 2, prev = Generic1<T>, last, serde)]
+    #[serde(bound = "T: Serialize + DeserializeOwned")]
+    struct Generic2<T: Debug + Serialize + DeserializeOwned> {
+        items: Vec<T>,
+    }
+
+    impl<T: Debug + Serialize + DeserializeOwned> From<Generic1<T>> for Generic2<T> {
+        fn from(val: Generic1<T>) -> Self {
+            Self {
+                items: vec![val.data],
+            }
+        }
+    }
+
+    #[derive(Debug, serde::Serialize, serde::Deserialize)]
+    struct Outer {
+        name: String,
+        #[serde(with = "crate::nested")]
+        inner: Test1,
+    }
+
+    def_schema!(Outer = [1]; serde);
+
+    /// The same as `Outer`, but with newer version of `inner`
+    #[derive(Debug, serde::Serialize, serde::Deserialize)]
+    struct OuterEvolved {
+        name: String,
+        #[serde(with = "crate::nested")]
+        inner: Test2,
+    }
+
+    def_schema!(OuterEvolved = [1]; serde);
+
     #[test]
     fn create_db() {
         let tmp = tempfile::tempdir().unwrap();
@@ -551,7 +1170,7 @@ mod test {
             .unwrap();
         // Remove parent
         let res = db.del(&get_path());
-        assert!(res.is_err())
+        assert!(matches!(res, Err(crate::Error::HasChildren { .. })))
     }
 
     #[test]
@@ -562,7 +1181,820 @@ mod test {
 
         // Create child
         let res = db.put(&(get_path() + "a"), Test1 { data: 1 });
-        assert!(res.is_err())
+        match res {
+            Err(crate::Error::ParentMissing { path, .. }) => {
+                assert_eq!(path.to_string(), "@root/test/a")
+            }
+            other => panic!("Unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn logger() {
+        use std::sync::{Arc, Mutex};
+
+        struct Collect(Arc<Mutex<Vec<String>>>);
+
+        impl slog::Drain for Collect {
+            type Ok = ();
+            type Err = slog::Never;
+
+            fn log(&self, record: &slog::Record, _: &slog::OwnedKVList) -> Result<(), slog::Never> {
+                self.0.lock().unwrap().push(record.msg().to_string());
+                Ok(())
+            }
+        }
+
+        let records = Arc::new(Mutex::new(Vec::new()));
+        let options = StorageOptions {
+            logger: Some(slog::Logger::root(Collect(records.clone()), slog::o!())),
+            ..Default::default()
+        };
+        let tmp = tempfile::tempdir().unwrap();
+        let db = Storage::connect_with(tmp.path(), options).unwrap();
+
+        db.put(&get_path(), Test2 { data: 1.0 }).epos(pos!()).unwrap();
+        db.put(&get_path(), Test1 { data: 1 }).epos(pos!()).unwrap();
+        let _: Option<Test1> = db.get(&get_path()).epos(pos!()).unwrap();
+        assert!(db.del(&(get_path() + "a")).is_err());
+
+        let records = records.lock().unwrap();
+        assert_eq!(
+            *records,
+            vec![
+                "put @root/test",
+                "overwriting newer version with older",
+                "put @root/test",
+                "get @root/test",
+                "del @root/test/a failed",
+            ]
+        );
+    }
+
+    #[test]
+    fn metrics() {
+        use metrics::Op;
+
+        let tmp = tempfile::tempdir().unwrap();
+        let db = Storage::connect(tmp.path()).unwrap();
+
+        db.put(&get_path(), Test1 { data: 1 }).epos(pos!()).unwrap();
+        db.put(&(get_path() + "a"), Test2 { data: 1.0 })
+            .epos(pos!())
+            .unwrap();
+        let _: Option<Test2> = db.get(&get_path()).epos(pos!()).unwrap();
+        let _: Option<Test1> = db.get(&(get_path() + "a")).epos(pos!()).unwrap();
+        let res: Result<Option<String>, _> = db.get(&get_path());
+        assert!(res.is_err());
+
+        let metrics = db.metrics();
+        assert_eq!(metrics.op(Op::Put).latency.count, 2);
+        assert_eq!(metrics.op(Op::Get).latency.count, 3);
+        assert_eq!(metrics.op(Op::Get).errors, 1);
+        assert_eq!(metrics.op(Op::Convert).latency.count, 2);
+        assert_eq!(metrics.upgrades, 1);
+        assert_eq!(metrics.downgrades, 1);
+        assert_eq!(metrics.decode_failures, 1);
+        assert!(metrics.bytes_read > 0);
+        assert!(metrics.bytes_written > 0);
+
+        let text = metrics.to_prometheus();
+        assert!(text.contains("lmtreedb_operations_total{op=\"get\"} 3\n"));
+        assert!(text.contains("lmtreedb_operation_duration_seconds_bucket{op=\"put\",le=\"+Inf\"} 2\n"));
+        assert!(text.contains("lmtreedb_schema_upgrades_total 1\n"));
+    }
+
+    path!(Typed = "typed" => Test2);
+    path!(Root -> Typed);
+
+    #[test]
+    fn typed_path() {
+        let tmp = tempfile::tempdir().unwrap();
+        let db = Storage::connect(tmp.path()).unwrap();
+
+        assert!(db.get_typed(path!([] / Typed)).epos(pos!()).unwrap().is_none());
+        db.put_typed(path!([] / Typed), Test2 { data: 1.5 })
+            .epos(pos!())
+            .unwrap();
+        let data = db.get_typed(path!([] / Typed)).epos(pos!()).unwrap().unwrap();
+        assert_eq!(data.data, 1.5);
+    }
+
+    #[test]
+    fn slashes_in_names() {
+        let tmp = tempfile::tempdir().unwrap();
+        let db = Storage::connect(tmp.path()).unwrap();
+
+        let nested = Path::parse("/a/b");
+        let slashed = Path::parse("/a\\/b");
+        db.put(&Path::parse("/a"), ()).epos(pos!()).unwrap();
+        db.put(&nested, Test1 { data: 1 }).epos(pos!()).unwrap();
+        db.put(&slashed, Test1 { data: 2 }).epos(pos!()).unwrap();
+        assert_eq!(db.get::<Test1>(&nested).epos(pos!()).unwrap().unwrap().data, 1);
+        assert_eq!(db.get::<Test1>(&slashed).epos(pos!()).unwrap().unwrap().data, 2);
+
+        let found = db.glob("**").epos(pos!()).unwrap();
+        let root = Root::default().path();
+        assert_eq!(found, vec![root.clone(), Path::parse("/a"), nested.clone(), slashed.clone()]);
+
+        db.del(&slashed).epos(pos!()).unwrap();
+        assert_eq!(db.get::<Test1>(&nested).epos(pos!()).unwrap().unwrap().data, 1);
+        let info: DataWrapperV1 = db.children(&root).epos(pos!()).unwrap().unwrap();
+        assert_eq!(info.children.into_iter().collect::<Vec<_>>(), vec!["a".to_string()]);
+    }
+
+    #[test]
+    fn glob() {
+        let tmp = tempfile::tempdir().unwrap();
+        let db = Storage::connect(tmp.path()).unwrap();
+
+        let root = Root::default().path();
+        for user in &["1", "2"] {
+            let user = root.clone() + "users" + user;
+            db.put(&user.parent().unwrap().into(), ()).epos(pos!()).unwrap();
+            db.put(&user, ()).epos(pos!()).unwrap();
+            db.put(&(user.clone() + "sessions"), ()).epos(pos!()).unwrap();
+            db.put(&(user.clone() + "sessions/a"), Test2 { data: 1.0 })
+                .epos(pos!())
+                .unwrap();
+        }
+        db.put(&(root.clone() + "sessions"), ()).epos(pos!()).unwrap();
+
+        let found = db.glob("users/*/sessions/**").epos(pos!()).unwrap();
+        let expected: Vec<Path> = vec![
+            "/users/1/sessions",
+            "/users/1/sessions/a",
+            "/users/2/sessions",
+            "/users/2/sessions/a",
+        ]
+        .into_iter()
+        .map(Path::parse)
+        .collect();
+        assert_eq!(found, expected);
+
+        let found = db.glob("**/sessions").epos(pos!()).unwrap();
+        assert_eq!(found.len(), 3);
+        let found = db.glob("users/[!1]").epos(pos!()).unwrap();
+        assert_eq!(found, vec![Path::parse("/users/2")]);
+
+        // Values of other families in the same subtree are skipped
+        db.put(&Path::parse("/users/1/sessions/b"), "text".to_string()).epos(pos!()).unwrap();
+        db.put(&Path::parse("/users/2/sessions/b"), vec![1u8]).epos(pos!()).unwrap();
+        let found: Vec<(Path, Test2)> =
+            db.glob_typed("users/*/sessions/*").epos(pos!()).unwrap();
+        assert_eq!(found.len(), 2);
+        assert_eq!(found[1].0, Path::parse("/users/2/sessions/a"));
+        assert_eq!(found[1].1.data, 1.0);
+    }
+
+    #[test]
+    fn roots() {
+        let tmp = tempfile::tempdir().unwrap();
+        let db = Storage::connect(tmp.path()).unwrap();
+
+        let names = |db: &Storage| -> Vec<String> {
+            let roots = db.roots().epos(pos!()).unwrap();
+            roots.iter().map(|x| x.name().to_string()).collect()
+        };
+        assert_eq!(names(&db), vec!["@root"]);
+
+        db.put(&Path::parse("@system/a"), Test1 { data: 1 })
+            .epos(pos!())
+            .unwrap();
+        db.put(&Root::named("user").path(), ()).epos(pos!()).unwrap();
+        assert_eq!(names(&db), vec!["@root", "@system", "@user"]);
+        assert!(db.get::<()>(&Path::parse("@system")).epos(pos!()).unwrap().is_some());
+
+        // Deeper nodes still require their parents
+        let res = db.put(&Path::parse("@other/a/b"), ());
+        assert!(matches!(res, Err(crate::Error::ParentMissing { .. })));
+        assert!(db.put(&Path::parse("a"), ()).is_err());
+
+        let found = db.glob("@*/**").epos(pos!()).unwrap();
+        assert_eq!(found.len(), 4);
+    }
+
+    #[test]
+    fn roots_bypass_options() {
+        use hooks::Hooks;
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        use std::sync::Arc;
+
+        let triggered = Arc::new(AtomicUsize::new(0));
+        let counter = triggered.clone();
+        let hooks = Hooks::default()
+            .validator("@*", |_| Err(err!("Roots are not written by users")))
+            .unwrap()
+            .trigger("*", move |_, _| {
+                counter.fetch_add(1, Ordering::SeqCst);
+                Ok(())
+            })
+            .unwrap();
+        let options = StorageOptions {
+            constraints: vec![constraints::Constraint::new::<Test2>("**").unwrap()],
+            hooks,
+            ..Default::default()
+        };
+        let tmp = tempfile::tempdir().unwrap();
+        // Default root is created on connect
+        let db = Storage::connect_with(tmp.path(), options).unwrap();
+        assert!(db.get::<()>(&Root::default().path()).epos(pos!()).unwrap().is_some());
+
+        db.put(&Path::parse("@system/a"), Test2 { data: 1.0 }).epos(pos!()).unwrap();
+        assert_eq!(db.roots().epos(pos!()).unwrap().len(), 2);
+        assert_eq!(triggered.load(Ordering::SeqCst), 0);
+
+        // Values of users are still checked
+        db.put(&Path::parse("/a"), Test2 { data: 1.0 }).epos(pos!()).unwrap();
+        assert_eq!(triggered.load(Ordering::SeqCst), 1);
+        assert!(db.put(&Path::parse("/b"), 1u64).is_err());
+    }
+
+    #[test]
+    fn scope() {
+        let tmp = tempfile::tempdir().unwrap();
+        let db = Storage::connect(tmp.path()).unwrap();
+
+        db.put(&get_path(), ()).epos(pos!()).unwrap();
+        db.put(&(get_path() + "secret"), Test1 { data: 1 })
+            .epos(pos!())
+            .unwrap();
+        db.put(&(get_path() + "plugin"), ()).epos(pos!()).unwrap();
+
+        let scope = db.scope(&(get_path() + "plugin"));
+        scope.put(&Path::parse("a"), Test1 { data: 2 }).epos(pos!()).unwrap();
+        let data: Test1 = scope.get(&Path::parse("./x/../a")).epos(pos!()).unwrap().unwrap();
+        assert_eq!(data.data, 2);
+        let data: Test1 = db.get(&(get_path() + "plugin/a")).epos(pos!()).unwrap().unwrap();
+        assert_eq!(data.data, 2);
+
+        for escape in &["../secret", "a/../../secret", "/test/secret"] {
+            let res: Result<Option<Test1>, _> = scope.get(&Path::parse(escape));
+            assert!(matches!(res, Err(crate::Error::OutOfScope { .. })), "{}", escape);
+        }
+        assert!(scope.del(&Path::parse("..")).is_err());
+
+        let nested = scope.scope(&Path::parse("a")).epos(pos!()).unwrap();
+        assert_eq!(nested.root(), &(get_path() + "plugin/a"));
+        assert!(nested.get::<Test1>(&Path::parse("..")).is_err());
+        let data: Test1 = nested.get(&Path::new()).epos(pos!()).unwrap().unwrap();
+        assert_eq!(data.data, 2);
+    }
+
+    #[test]
+    fn constraints() {
+        let tmp = tempfile::tempdir().unwrap();
+        let options = StorageOptions {
+            constraints: vec![
+                constraints::Constraint::new::<Test2>("test/*").unwrap(),
+                constraints::Constraint::new::<Test2>("test/new").unwrap().versions(2..),
+            ],
+            ..Default::default()
+        };
+        let db = Storage::connect_with(tmp.path(), options).unwrap();
+
+        db.put(&get_path(), ()).epos(pos!()).unwrap();
+        db.put(&(get_path() + "a"), Test1 { data: 1 }).epos(pos!()).unwrap();
+        db.put(&(get_path() + "b"), Test2 { data: 1.0 })
+            .epos(pos!())
+            .unwrap();
+        let res = db.put(&(get_path() + "c"), Short1 { data: 1 });
+        match res {
+            Err(crate::Error::TypeMismatch { path, expected, .. }) => {
+                assert_eq!(path, get_path() + "c");
+                assert!(expected.ends_with("Test2"));
+            }
+            other => panic!("{:?}", other),
+        }
+        let res = db.put(&(get_path() + "new"), Test1 { data: 1 });
+        assert!(matches!(res, Err(crate::Error::TypeMismatch { .. })));
+        assert!(db.get::<()>(&(get_path() + "c")).epos(pos!()).unwrap().is_none());
+    }
+
+    #[test]
+    fn hooks() {
+        use hooks::{Hooks, WriteOp};
+
+        let hooks = Hooks::default()
+            .validator("test/*", |event| match event.decode::<Test1>()? {
+                Some(val) if val.data < 0 => Err(err!("Negative value")),
+                _ => Ok(()),
+            })
+            .unwrap()
+            .validator("test/*", |event| match event.op {
+                WriteOp::Del if event.path.file_name() == Some("keep") => Err(err!("Cannot del")),
+                _ => Ok(()),
+            })
+            .unwrap()
+            .trigger("test/*", |txn, event| {
+                // Parent keeps the number of its children
+                let parent: Path = event.path.parent().unwrap().into();
+                let info: DataWrapperV1 = txn.children(&parent)?.unwrap();
+                txn.put(&parent, info.children.len() as u64)
+            })
+            .unwrap()
+            .trigger("test/loop", |txn, event| txn.put(&event.path, ()))
+            .unwrap();
+        let options = StorageOptions {
+            hooks,
+            ..Default::default()
+        };
+        let tmp = tempfile::tempdir().unwrap();
+        let db = Storage::connect_with(tmp.path(), options).unwrap();
+
+        db.put(&get_path(), 0u64).epos(pos!()).unwrap();
+        db.put(&(get_path() + "a"), Test1 { data: 1 }).epos(pos!()).unwrap();
+        db.put(&(get_path() + "keep"), Test1 { data: 2 })
+            .epos(pos!())
+            .unwrap();
+        assert_eq!(db.get::<u64>(&get_path()).epos(pos!()).unwrap(), Some(2));
+
+        let res = db.put(&(get_path() + "b"), Test1 { data: -1 });
+        assert!(matches!(res, Err(crate::Error::Rejected { .. })));
+        let res = db.del(&(get_path() + "keep"));
+        assert!(matches!(res, Err(crate::Error::Rejected { .. })));
+
+        db.del(&(get_path() + "a")).epos(pos!()).unwrap();
+        assert_eq!(db.get::<u64>(&get_path()).epos(pos!()).unwrap(), Some(1));
+
+        // Whole transaction is aborted
+        assert!(db.put(&(get_path() + "loop"), ()).is_err());
+        assert!(db.get::<()>(&(get_path() + "loop")).epos(pos!()).unwrap().is_none());
+        assert_eq!(db.get::<u64>(&get_path()).epos(pos!()).unwrap(), Some(1));
+    }
+
+    #[test]
+    fn metrics_conversions() {
+        use hooks::Hooks;
+        use index::Index;
+
+        let hooks = Hooks::default()
+            .validator("test/*", |event| event.decode::<Test2>().map(|_| ()))
+            .unwrap()
+            .trigger("test/*", |txn, event| txn.get::<Test2>(&event.path).map(|_| ()))
+            .unwrap();
+        let options = StorageOptions {
+            hooks,
+            indexes: vec![Index::new::<Test2, _>("data", "test/*", |x| vec![x.data.to_string()])
+                .unwrap()],
+            ..Default::default()
+        };
+        let tmp = tempfile::tempdir().unwrap();
+        let db = Storage::connect_with(tmp.path(), options).unwrap();
+
+        db.put(&get_path(), ()).epos(pos!()).unwrap();
+        db.put(&(get_path() + "a"), Test1 { data: 1 }).epos(pos!()).unwrap();
+        // Validator, index and trigger
+        assert_eq!(db.metrics().upgrades, 3);
+
+        let found = db.glob_typed::<Test2>("test/*").epos(pos!()).unwrap();
+        assert_eq!(found.len(), 1);
+        assert_eq!(db.metrics().upgrades, 4);
+        assert_eq!(db.metrics().op(metrics::Op::Convert).latency.count, 4);
+    }
+
+    #[test]
+    fn indexes() {
+        use index::Index;
+
+        let tmp = tempfile::tempdir().unwrap();
+        {
+            let db = Storage::connect(tmp.path()).unwrap();
+            db.put(&get_path(), ()).epos(pos!()).unwrap();
+            db.put(&(get_path() + "old"), Test1 { data: 1 }).epos(pos!()).unwrap();
+        }
+
+        let by_sign = Index::new::<Test2, _>("sign", "test/*", |val| {
+            vec![if val.data < 0.0 { "negative" } else { "positive" }.to_string()]
+        });
+        let options = StorageOptions {
+            indexes: vec![by_sign.unwrap()],
+            ..Default::default()
+        };
+        let db = Storage::connect_with(tmp.path(), options).unwrap();
+        let lookup = |key| db.lookup("sign", key).epos(pos!()).unwrap();
+
+        // Nodes written before the index was added are found only after reindex
+        assert!(lookup("positive").is_empty());
+        assert_eq!(db.reindex("sign").epos(pos!()).unwrap(), 1);
+        assert_eq!(lookup("positive"), vec![get_path() + "old"]);
+
+        // Older versions are upgraded before extracting keys, other families are skipped
+        db.put(&(get_path() + "a"), Test1 { data: -1 }).epos(pos!()).unwrap();
+        db.put(&(get_path() + "b"), Test2 { data: 2.0 }).epos(pos!()).unwrap();
+        db.put(&(get_path() + "c"), ()).epos(pos!()).unwrap();
+        assert_eq!(lookup("negative"), vec![get_path() + "a"]);
+        assert_eq!(lookup("positive"), vec![get_path() + "b", get_path() + "old"]);
+
+        db.put(&(get_path() + "a"), Test2 { data: 3.0 }).epos(pos!()).unwrap();
+        db.del(&(get_path() + "old")).epos(pos!()).unwrap();
+        assert!(lookup("negative").is_empty());
+        assert_eq!(lookup("positive"), vec![get_path() + "a", get_path() + "b"]);
+
+        assert_eq!(db.reindex("sign").epos(pos!()).unwrap(), 2);
+        assert_eq!(lookup("positive"), vec![get_path() + "a", get_path() + "b"]);
+        assert!(db.lookup("missing", "positive").is_err());
+        assert_eq!(db.stats().epos(pos!()).unwrap().databases[1].entries, 2);
+    }
+
+    #[test]
+    fn stats() {
+        let tmp = tempfile::tempdir().unwrap();
+        let db = Storage::connect(tmp.path()).unwrap();
+
+        db.put(&get_path(), Test1 { data: 1 }).epos(pos!()).unwrap();
+        db.put(&(get_path() + "a"), Test2 { data: 1.0 })
+            .epos(pos!())
+            .unwrap();
+        db.put(&(get_path() + "b"), Test2 { data: 2.0 })
+            .epos(pos!())
+            .unwrap();
+
+        let stats = db.stats().epos(pos!()).unwrap();
+        // Three nodes, the default root and the list of roots
+        assert_eq!(stats.databases[0].entries, 5);
+        assert!(stats.used_bytes() <= stats.map_size);
+        assert!(stats.last_txn_id > 0);
+
+        let usage = db.du(&get_path()).epos(pos!()).unwrap();
+        assert_eq!(usage.nodes, 3);
+        assert!(usage.bytes > 0);
+        assert_eq!(usage.versions.into_iter().collect::<Vec<_>>(), vec![(1, 1), (2, 2)]);
+
+        let usage = db.du(&(get_path() + "none")).epos(pos!()).unwrap();
+        assert_eq!(usage.nodes, 0);
+    }
+
+    #[test]
+    fn error_kinds() {
+        let tmp = tempfile::tempdir().unwrap();
+        let path = tmp.path();
+        let db = Storage::connect(path).unwrap();
+
+        let res = db.del(&get_path());
+        assert!(matches!(res, Err(crate::Error::NotFound { .. })));
+
+        db.put(&get_path(), Test1 { data: 1 }).epos(pos!()).unwrap();
+        let res: Result<Option<String>, _> = db.get(&get_path());
+        assert!(matches!(res, Err(crate::Error::Decode { .. })));
+
+        db.put(&get_path(), Test2 { data: 1.0 }).epos(pos!()).unwrap();
+        let res: Result<Option<String>, _> = db.get(&get_path());
+        match res {
+            Err(crate::Error::VersionChain {
+                stored, requested, ..
+            }) => assert_eq!((stored, requested), (2, 1)),
+            other => panic!("Unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn std_error() {
+        use std::error::Error as _;
+
+        let tmp = tempfile::tempdir().unwrap();
+        let db = Storage::connect(tmp.path()).unwrap();
+
+        let del = |path: &Path| -> Result<(), Box<dyn std::error::Error>> {
+            db.del(path)?;
+            Ok(())
+        };
+        let err = del(&get_path()).unwrap_err();
+        assert!(err.to_string().starts_with(&format!("'{}' is not found", get_path())));
+        let err = err.downcast::<crate::Error>().unwrap();
+        assert_eq!(err.kind(), "NotFound");
+
+        // Every position of the trace is a separate source
+        let mut sources = Vec::new();
+        let mut source = err.source();
+        while let Some(x) = source {
+            sources.push(x.to_string());
+            source = x.source();
+        }
+        assert_eq!(sources.len(), err.trace().iter().count());
+        assert!(sources.iter().all(|x| x.starts_with("<src/lib.rs:")), "{:?}", sources);
+        assert!(err.source().unwrap().is::<my_error::ErrWrapper>());
+
+        let json: serde_json::Value = serde_json::from_str(&err.to_json()).unwrap();
+        assert_eq!(json["kind"], "NotFound");
+        assert_eq!(json["message"], format!("'{}' is not found", get_path()));
+        assert_eq!(json["path"], serde_json::json!(["@root", "test"]));
+        let trace = json["trace"].as_array().unwrap();
+        assert_eq!(trace.len(), sources.len());
+        assert_eq!(trace[0]["file"], "src/lib.rs");
+        assert!(trace[0]["line"].is_u64());
+
+        let msgpack: serde_json::Value = rmp_serde::from_slice(&err.to_msgpack()).unwrap();
+        assert_eq!(msgpack, json);
+
+        let err = crate::Error::Lmdb {
+            path: None,
+            error: lmdb::Error::MapFull,
+            trace: err!("Full"),
+        };
+        assert!(err.source().unwrap().to_string().ends_with("> Full"));
+        assert!(err.to_json().contains("\"path\":null"));
+
+        // Text of the error is kept by `my_error::Error`
+        let del = |path: &Path| -> Result<(), my_error::Error> {
+            db.del(path)?;
+            Ok(())
+        };
+        assert!(del(&get_path()).unwrap_err().to_string().contains("is not found"));
+
+        // `my_error::Error` keeps the chain as sources too
+        let chained = || -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+            Err(err!("First")).epos(pos!())?;
+            Ok(())
+        };
+        let err = chained().unwrap_err();
+        assert!(err.to_string().starts_with("<src/lib.rs:"));
+        assert!(err.source().unwrap().to_string().ends_with("> First"));
+        assert!(err.source().unwrap().source().is_none());
+    }
+
+    #[test]
+    fn backtraces() {
+        my_error::capture_backtraces(true);
+        let err = err!("Failed").chain(Error::pos(pos!()()));
+        my_error::capture_backtraces(false);
+        assert!(err.backtrace().is_some());
+        assert!(err!("Failed").backtrace().is_none());
+
+        let json = serde_json::to_value(&err).unwrap();
+        assert!(json[0]["backtrace"].is_string());
+        assert!(json[1]["backtrace"].is_null());
+    }
+
+    #[test]
+    fn migrate() {
+        let tmp = tempfile::tempdir().unwrap();
+        let path = tmp.path();
+        let db = Storage::connect(path).unwrap();
+
+        db.put(&get_path(), Test1 { data: 1 }).epos(pos!()).unwrap();
+        db.put(&(get_path() + "a"), Test1 { data: 2 })
+            .epos(pos!())
+            .unwrap();
+        db.put(&(get_path() + "b"), Test2 { data: 3.0 })
+            .epos(pos!())
+            .unwrap();
+
+        let report = db.migrate_dry_run::<Test2>("test/**").epos(pos!()).unwrap();
+        assert_eq!(report.versions.get(&1), Some(&2));
+        assert_eq!(report.versions.get(&2), Some(&1));
+        assert_eq!(report.migrated, 2);
+        assert!(report.failures.is_empty());
+        let info: DataWrapperV1 = db.children(&get_path()).epos(pos!()).unwrap().unwrap();
+        assert_eq!(info.version, Test1::version());
+
+        let report = db.migrate::<Test2>("test/**").epos(pos!()).unwrap();
+        assert_eq!(report.migrated, 2);
+        let info: DataWrapperV1 = db.children(&get_path()).epos(pos!()).unwrap().unwrap();
+        assert_eq!(info.version, Test2::version());
+        assert!(info.children.contains("a"));
+
+        let data: Test2 = db.get(&(get_path() + "a")).epos(pos!()).unwrap().unwrap();
+        assert_eq!(data.data, 2.0);
+    }
+
+    #[test]
+    fn migrate_scoped() {
+        let tmp = tempfile::tempdir().unwrap();
+        let db = Storage::connect(tmp.path()).unwrap();
+
+        db.put(&get_path(), ()).epos(pos!()).unwrap();
+        db.put(&(get_path() + "item1"), Test1 { data: 1 }).epos(pos!()).unwrap();
+        db.put(&(get_path() + "item2"), Test1 { data: 2 }).epos(pos!()).unwrap();
+        // Counter has the same version as `Test1` and can be loaded as it
+        db.put(&(get_path() + "count"), 7u64).epos(pos!()).unwrap();
+
+        let report = db.migrate::<Test2>("test/item*").epos(pos!()).unwrap();
+        assert_eq!(report.migrated, 2);
+        assert_eq!(report.versions.get(&1), Some(&2));
+
+        let info: DataWrapperV1 =
+            db.children(&(get_path() + "count")).epos(pos!()).unwrap().unwrap();
+        assert_eq!(info.version, u64::version());
+        assert_eq!(info.data, Value::from(7u64));
+        let count: u64 = db.get(&(get_path() + "count")).epos(pos!()).unwrap().unwrap();
+        assert_eq!(count, 7);
+    }
+
+    #[test]
+    fn migrate_skips() {
+        let tmp = tempfile::tempdir().unwrap();
+        let db = Storage::connect(tmp.path()).unwrap();
+
+        db.put(&get_path(), ()).epos(pos!()).unwrap();
+        db.put(&(get_path() + "a"), Test1 { data: 1 }).epos(pos!()).unwrap();
+        db.put(&(get_path() + "b"), Test2 { data: 2.0 }).epos(pos!()).unwrap();
+        db.put(&(get_path() + "c"), Short3 { data: 3 }).epos(pos!()).unwrap();
+
+        // Newer version is not downgraded, other family is not touched
+        let report = db.migrate::<Test1>("test/**").epos(pos!()).unwrap();
+        assert_eq!(report.migrated, 0);
+        assert_eq!(report.newer, vec![get_path() + "b"]);
+        assert_eq!(report.skipped, 1);
+        assert!(report.failures.is_empty());
+        let info: DataWrapperV1 =
+            db.children(&(get_path() + "b")).epos(pos!()).unwrap().unwrap();
+        assert_eq!(info.version, Test2::version());
+
+        // Container has the same version as `Test1`, but it is not a failure
+        let report = db.migrate::<Test2>("test/**").epos(pos!()).unwrap();
+        assert_eq!(report.migrated, 1);
+        assert!(report.newer.is_empty());
+        assert_eq!(report.skipped, 2);
+        assert!(report.failures.is_empty());
+        let info: DataWrapperV1 =
+            db.children(&(get_path() + "c")).epos(pos!()).unwrap().unwrap();
+        assert_eq!(info.version, Short3::version());
+    }
+
+    #[test]
+    fn upgrade_shortcut() {
+        let tmp = tempfile::tempdir().unwrap();
+        let path = tmp.path();
+        let db = Storage::connect(path).unwrap();
+
+        // There is a shortcut from the first version
+        db.put(&get_path(), Short1 { data: 1 }).epos(pos!()).unwrap();
+        let data: Short3 = db.get(&get_path()).epos(pos!()).unwrap().unwrap();
+        assert_eq!(data.data, 101);
+
+        // But not from the second one
+        db.put(&get_path(), Short2 { data: 1 }).epos(pos!()).unwrap();
+        let data: Short3 = db.get(&get_path()).epos(pos!()).unwrap().unwrap();
+        assert_eq!(data.data, 11);
+    }
+
+    #[test]
+    fn derive() {
+        let tmp = tempfile::tempdir().unwrap();
+        let path = tmp.path();
+        let db = Storage::connect(path).unwrap();
+
+        db.put(&get_path(), Derived1 { data: 3 }).epos(pos!()).unwrap();
+        let data: Derived2 = db.get(&get_path()).epos(pos!()).unwrap().unwrap();
+        assert_eq!(data.data, 3.0);
+    }
+
+    #[test]
+    fn derive_generic() {
+        let tmp = tempfile::tempdir().unwrap();
+        let path = tmp.path();
+        let db = Storage::connect(path).unwrap();
+
+        db.put(&get_path(), Generic1 { data: "a".to_string() })
+            .epos(pos!())
+            .unwrap();
+        let data: Generic2<String> = db.get(&get_path()).epos(pos!()).unwrap().unwrap();
+        assert_eq!(data.items, vec!["a".to_string()]);
+
+        db.put(&get_path(), Generic2 { items: vec![1u8, 2] })
+            .epos(pos!())
+            .unwrap();
+        let data: Generic1<u8> = db.get(&get_path()).epos(pos!()).unwrap().unwrap();
+        assert_eq!(data.data, 1);
+    }
+
+    #[test]
+    fn collections() {
+        let tmp = tempfile::tempdir().unwrap();
+        let path = tmp.path();
+        let db = Storage::connect(path).unwrap();
+
+        db.put(&get_path(), vec![Test1 { data: 1 }, Test1 { data: 2 }])
+            .epos(pos!())
+            .unwrap();
+        let data: Vec<Test2> = db.get(&get_path()).epos(pos!()).unwrap().unwrap();
+        assert_eq!(data.iter().map(|x| x.data).collect::<Vec<_>>(), vec![1.0, 2.0]);
+        let data: [Test2; 2] = db.get(&get_path()).epos(pos!()).unwrap().unwrap();
+        assert_eq!(data[1].data, 2.0);
+
+        let mut map = std::collections::HashMap::new();
+        map.insert("a".to_string(), Test2 { data: 3.5 });
+        db.put(&get_path(), map).epos(pos!()).unwrap();
+        let data: std::collections::BTreeMap<String, Test1> =
+            db.get(&get_path()).epos(pos!()).unwrap().unwrap();
+        assert_eq!(data["a"].data, 3);
+
+        db.put(&get_path(), (Some(Test1 { data: 4 }), None::<Test1>, 5u8))
+            .epos(pos!())
+            .unwrap();
+        let data: (Option<Test2>, Option<Test2>, u8) =
+            db.get(&get_path()).epos(pos!()).unwrap().unwrap();
+        assert_eq!(data.0.unwrap().data, 4.0);
+        assert!(data.1.is_none());
+        assert_eq!(data.2, 5);
+    }
+
+    #[test]
+    fn legacy_bytes() {
+        // `Vec<u8>` was saved as a plain array of the first version
+        let raw = Value::Array(vec![Value::from(1u8), Value::from(2u8)]);
+        let data: Vec<u8> = load(1, raw.clone()).epos(pos!()).unwrap();
+        assert_eq!(data, vec![1, 2]);
+        assert_eq!(<Vec<u8>>::version(), 2);
+
+        // New format is not guessed from the shape of the old one
+        let raw = Value::Array(vec![Value::from(1u8), Value::Array(vec![Value::from(2u8)])]);
+        assert!(load::<Vec<u8>>(1, raw).is_err());
+
+        // And can be written back for older readers
+        let data: collections::VecV1<u8> = load(2, vec![1u8, 2].save().unwrap()).unwrap();
+        assert_eq!(data.save().unwrap(), Value::Array(vec![Value::from(1u8), Value::from(2u8)]));
+    }
+
+    #[test]
+    fn legacy_keys() {
+        let tmp = tempfile::tempdir().unwrap();
+        let db = Storage::connect(tmp.path()).unwrap();
+        db.put(&get_path(), ()).epos(pos!()).unwrap();
+        db.put(&(get_path() + "plain"), Test1 { data: 3 }).epos(pos!()).unwrap();
+
+        // Keys were written by joining names with slashes, backslashes were kept as is
+        let ro = db.env.begin_ro_txn().unwrap();
+        let raw = lmdb::Transaction::get(&ro, db.db, &"@root/test/plain").unwrap().to_vec();
+        ro.commit().unwrap();
+        let mut rw = db.env.begin_rw_txn().unwrap();
+        let key = ["@root", "test", "a\\b"].join("/");
+        rw.put(db.db, &key, &raw, lmdb::WriteFlags::empty()).unwrap();
+        rw.commit().unwrap();
+
+        let path = Path::parse("/test/a\\b");
+        assert_eq!(path.file_name(), Some("a\\b"));
+        let data: Test1 = db.get(&path).epos(pos!()).unwrap().unwrap();
+        assert_eq!(data.data, 3);
+    }
+
+    #[test]
+    fn nested() {
+        let tmp = tempfile::tempdir().unwrap();
+        let path = tmp.path();
+        let db = Storage::connect(path).unwrap();
+
+        let data = Outer {
+            name: "a".to_string(),
+            inner: Test1 { data: 7 },
+        };
+        db.put(&get_path(), data).epos(pos!()).unwrap();
+
+        let data: OuterEvolved = db.get(&get_path()).epos(pos!()).unwrap().unwrap();
+        assert_eq!(data.name, "a");
+        assert_eq!(data.inner.data, 7.0);
+    }
+
+    #[test]
+    fn write_back() {
+        let tmp = tempfile::tempdir().unwrap();
+        let path = tmp.path();
+        let options = StorageOptions {
+            write_back: true,
+            ..Default::default()
+        };
+        let db = Storage::connect_with(path, options).unwrap();
+
+        db.put(&get_path(), Test1 { data: 4 }).epos(pos!()).unwrap();
+
+        // Reading older version does not change anything
+        let data: Test1 = db.get(&get_path()).epos(pos!()).unwrap().unwrap();
+        assert_eq!(data.data, 4);
+        let info: DataWrapperV1 = db.children(&get_path()).epos(pos!()).unwrap().unwrap();
+        assert_eq!(info.version, Test1::version());
+
+        let data: Test2 = db.get(&get_path()).epos(pos!()).unwrap().unwrap();
+        assert_eq!(data.data, 4.0);
+        let info: DataWrapperV1 = db.children(&get_path()).epos(pos!()).unwrap().unwrap();
+        assert_eq!(info.version, Test2::version());
+    }
+
+    #[test]
+    fn hooks_write_back() {
+        use hooks::Hooks;
+
+        let hooks = Hooks::default()
+            .trigger("test/touch", |txn, _| {
+                let data: Test2 = txn.get(&(get_path() + "a"))?.unwrap();
+                assert_eq!(data.data, 4.0);
+                Ok(())
+            })
+            .unwrap();
+        let options = StorageOptions {
+            hooks,
+            write_back: true,
+            ..Default::default()
+        };
+        let tmp = tempfile::tempdir().unwrap();
+        let db = Storage::connect_with(tmp.path(), options).unwrap();
+
+        db.put(&get_path(), ()).epos(pos!()).unwrap();
+        db.put(&(get_path() + "a"), Test1 { data: 4 }).epos(pos!()).unwrap();
+        db.put(&(get_path() + "touch"), ()).epos(pos!()).unwrap();
+        let info: DataWrapperV1 =
+            db.children(&(get_path() + "a")).epos(pos!()).unwrap().unwrap();
+        assert_eq!(info.version, Test2::version());
     }
 
     fn get_path() -> Path {
@@ -0,0 +1,251 @@
+//! Counters and latency histograms of `Storage` operations. See `Storage::metrics`.
+
+use std::fmt::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+use crate::*;
+
+/// Upper bounds of histogram buckets, in microseconds
+const BUCKETS: [u64; 12] = [
+    10, 50, 100, 250, 500, 1_000, 2_500, 5_000, 10_000, 50_000, 100_000, 1_000_000,
+];
+
+/// Operations with their own counters and histograms
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Op {
+    Get,
+    Put,
+    Del,
+    Migrate,
+    /// Upgrades and downgrades of loaded values, included into other operations
+    Convert,
+}
+
+impl Op {
+    const ALL: [Op; 5] = [Op::Get, Op::Put, Op::Del, Op::Migrate, Op::Convert];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Op::Get => "get",
+            Op::Put => "put",
+            Op::Del => "del",
+            Op::Migrate => "migrate",
+            Op::Convert => "convert",
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct Histogram {
+    /// Not cumulative, last one is for values greater than all `BUCKETS`
+    buckets: [AtomicU64; BUCKETS.len() + 1],
+    sum_us: AtomicU64,
+}
+
+impl Histogram {
+    fn record(&self, duration: Duration) {
+        let us = duration.as_micros() as u64;
+        let idx = BUCKETS.iter().position(|&x| us <= x).unwrap_or(BUCKETS.len());
+        self.buckets[idx].fetch_add(1, Ordering::Relaxed);
+        self.sum_us.fetch_add(us, Ordering::Relaxed);
+    }
+
+    fn snapshot(&self) -> HistogramSnapshot {
+        let mut total = 0;
+        let mut buckets = Vec::with_capacity(BUCKETS.len());
+        for (i, bucket) in self.buckets.iter().enumerate() {
+            total += bucket.load(Ordering::Relaxed);
+            if let Some(&le) = BUCKETS.get(i) {
+                buckets.push((le, total));
+            }
+        }
+        HistogramSnapshot {
+            buckets,
+            count: total,
+            sum_us: self.sum_us.load(Ordering::Relaxed),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct OpMetrics {
+    errors: AtomicU64,
+    latency: Histogram,
+}
+
+/// Live metrics of single `Storage`
+#[derive(Debug, Default)]
+pub(crate) struct Metrics {
+    ops: [OpMetrics; Op::ALL.len()],
+    upgrades: AtomicU64,
+    downgrades: AtomicU64,
+    decode_failures: AtomicU64,
+    bytes_read: AtomicU64,
+    bytes_written: AtomicU64,
+}
+
+impl Metrics {
+    /// Records finished operation. `res` contains number of written bytes.
+    pub fn op(&self, op: Op, duration: Duration, res: Result<usize, &Error>) {
+        let metrics = &self.ops[op as usize];
+        metrics.latency.record(duration);
+        match res {
+            Ok(bytes) => {
+                self.bytes_written.fetch_add(bytes as u64, Ordering::Relaxed);
+            }
+            Err(e) => {
+                metrics.errors.fetch_add(1, Ordering::Relaxed);
+                if let Error::Decode { .. } | Error::VersionChain { .. } = e {
+                    self.decode_failures.fetch_add(1, Ordering::Relaxed);
+                }
+            }
+        }
+    }
+
+    pub fn read(&self, bytes: usize) {
+        self.bytes_read.fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+
+    /// Records successful loading of `T` from stored `version`
+    pub fn loaded<T: Schema>(&self, version: u64, duration: Duration) {
+        if version == T::version() {
+            return;
+        }
+        self.upgrades.fetch_add(upgrades::<T>(version), Ordering::Relaxed);
+        self.downgrades
+            .fetch_add(version.saturating_sub(T::version()), Ordering::Relaxed);
+        self.ops[Op::Convert as usize].latency.record(duration);
+    }
+
+    pub fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            ops: Op::ALL
+                .iter()
+                .map(|&op| {
+                    let metrics = &self.ops[op as usize];
+                    OpSnapshot {
+                        op,
+                        errors: metrics.errors.load(Ordering::Relaxed),
+                        latency: metrics.latency.snapshot(),
+                    }
+                })
+                .collect(),
+            upgrades: self.upgrades.load(Ordering::Relaxed),
+            downgrades: self.downgrades.load(Ordering::Relaxed),
+            decode_failures: self.decode_failures.load(Ordering::Relaxed),
+            bytes_read: self.bytes_read.load(Ordering::Relaxed),
+            bytes_written: self.bytes_written.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// Number of upgrades `load` does to convert `version` to `T`, taking shortcuts into account
+fn upgrades<T: Schema>(version: u64) -> u64 {
+    if version >= T::version() || T::PrevVersion::version() >= T::version() {
+        return 0;
+    }
+    if T::shortcut(version).is_some() {
+        return 1;
+    }
+    1 + upgrades::<T::PrevVersion>(version)
+}
+
+#[derive(Clone, Debug)]
+pub struct HistogramSnapshot {
+    /// Upper bound in microseconds and number of values less or equal to it
+    pub buckets: Vec<(u64, u64)>,
+    pub count: u64,
+    pub sum_us: u64,
+}
+
+#[derive(Clone, Debug)]
+pub struct OpSnapshot {
+    pub op: Op,
+    /// Number of failed operations. Total number is `latency.count`
+    pub errors: u64,
+    pub latency: HistogramSnapshot,
+}
+
+/// Metrics at some moment, returned by `Storage::metrics`
+#[derive(Clone, Debug)]
+pub struct Snapshot {
+    pub ops: Vec<OpSnapshot>,
+    /// Steps done by upgrades, including shortcuts
+    pub upgrades: u64,
+    pub downgrades: u64,
+    /// Failures caused by corrupted data or wrong schema
+    pub decode_failures: u64,
+    pub bytes_read: u64,
+    pub bytes_written: u64,
+}
+
+impl Snapshot {
+    pub fn op(&self, op: Op) -> &OpSnapshot {
+        &self.ops[op as usize]
+    }
+
+    /// Renders metrics in the Prometheus text format
+    pub fn to_prometheus(&self) -> String {
+        let mut res = String::new();
+        // Writing to String never fails
+        let _ = self.write_prometheus(&mut res);
+        res
+    }
+
+    fn write_prometheus(&self, f: &mut String) -> std::fmt::Result {
+        writeln!(f, "# TYPE lmtreedb_operations_total counter")?;
+        for op in &self.ops {
+            let name = op.op.name();
+            writeln!(f, "lmtreedb_operations_total{{op=\"{}\"}} {}", name, op.latency.count)?;
+        }
+        writeln!(f, "# TYPE lmtreedb_operation_errors_total counter")?;
+        for op in &self.ops {
+            let name = op.op.name();
+            writeln!(f, "lmtreedb_operation_errors_total{{op=\"{}\"}} {}", name, op.errors)?;
+        }
+
+        writeln!(f, "# TYPE lmtreedb_operation_duration_seconds histogram")?;
+        for op in &self.ops {
+            let name = op.op.name();
+            for (le, count) in &op.latency.buckets {
+                writeln!(
+                    f,
+                    "lmtreedb_operation_duration_seconds_bucket{{op=\"{}\",le=\"{}\"}} {}",
+                    name,
+                    *le as f64 / 1e6,
+                    count
+                )?;
+            }
+            writeln!(
+                f,
+                "lmtreedb_operation_duration_seconds_bucket{{op=\"{}\",le=\"+Inf\"}} {}",
+                name, op.latency.count
+            )?;
+            writeln!(
+                f,
+                "lmtreedb_operation_duration_seconds_sum{{op=\"{}\"}} {}",
+                name,
+                op.latency.sum_us as f64 / 1e6
+            )?;
+            writeln!(
+                f,
+                "lmtreedb_operation_duration_seconds_count{{op=\"{}\"}} {}",
+                name, op.latency.count
+            )?;
+        }
+
+        let counters = [
+            ("lmtreedb_schema_upgrades_total", self.upgrades),
+            ("lmtreedb_schema_downgrades_total", self.downgrades),
+            ("lmtreedb_decode_failures_total", self.decode_failures),
+            ("lmtreedb_read_bytes_total", self.bytes_read),
+            ("lmtreedb_written_bytes_total", self.bytes_written),
+        ];
+        for (name, value) in &counters {
+            writeln!(f, "# TYPE {} counter", name)?;
+            writeln!(f, "{} {}", name, value)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,174 @@
+use std::collections::BTreeMap;
+use std::time::Instant;
+
+use crate::*;
+
+use super::{load_node, query, LmdbResultExt, Metrics, Op, ResultExt};
+use path::glob::Glob;
+use schema::chain::{describe, Chain};
+
+/// How many nodes are rewritten in single transaction
+const BATCH_SIZE: usize = 512;
+
+/// Result of `Storage::migrate` or `Storage::migrate_dry_run`
+#[derive(Debug, Default)]
+pub struct MigrationReport {
+    /// How many nodes were found for each stored version, including already migrated ones
+    pub versions: BTreeMap<u64, usize>,
+
+    /// Number of nodes that were (or would be, for dry run) rewritten to the new version
+    pub migrated: usize,
+
+    /// Nodes that cannot be converted to the required version. They are left untouched.
+    pub failures: Vec<(Path, Error)>,
+
+    /// Nodes with versions of the family newer than the required one.
+    /// They are left untouched, because downgrades may lose data.
+    pub newer: Vec<Path>,
+
+    /// Number of nodes which belong to other schema families, including plain `()` containers
+    pub skipped: usize,
+}
+
+impl Storage {
+    /// Rewrites every node of the family of `T` matching the glob pattern to `T::version()`.
+    /// See `path::glob` for the pattern syntax.
+    ///
+    /// Stored values do not know their type, only the version. A value of another family
+    /// with the same version may be loaded as `T` too, so pattern must cover only nodes of `T`.
+    ///
+    /// Older nodes are loaded with all required upgrades and saved back,
+    /// so later reads do not pay for conversion anymore.
+    /// Newer nodes are never downgraded, they are listed in `MigrationReport::newer`.
+    /// Nodes of other families are not touched, see `schema::chain::describe` for the members.
+    /// Work is split into several transactions, each one rewrites at most `BATCH_SIZE` nodes.
+    ///
+    /// Nodes that cannot be loaded as `T` are not modified and listed in `MigrationReport::failures`.
+    pub fn migrate<T: Schema>(&self, pattern: &str) -> Result<MigrationReport, Error> {
+        self.migrate_impl::<T>(pattern, false).trace_pos(pos!(pattern))
+    }
+
+    /// Same as `migrate`, but does not write anything. Only checks what would be done.
+    pub fn migrate_dry_run<T: Schema>(&self, pattern: &str) -> Result<MigrationReport, Error> {
+        self.migrate_impl::<T>(pattern, true).trace_pos(pos!(pattern))
+    }
+
+    fn migrate_impl<T: Schema>(&self, pattern: &str, dry_run: bool) -> Result<MigrationReport, Error> {
+        let start = Instant::now();
+        let mut bytes = 0;
+        let res = Glob::parse(pattern)
+            .map_err(Error::from)
+            .and_then(|glob| self.migrate_batches::<T>(&glob, dry_run, &mut bytes));
+        let elapsed = start.elapsed();
+        self.metrics
+            .op(Op::Migrate, elapsed, res.as_ref().map(|_| bytes));
+
+        let report = res.trace_pos(pos!())?;
+        slog::info!(self.logger(), "migrate {}", pattern;
+            "op" => "migrate", "pattern" => pattern, "version" => T::version(),
+            "dry_run" => dry_run,
+            "migrated" => report.migrated, "failures" => report.failures.len(),
+            "bytes" => bytes, "duration_us" => elapsed.as_micros() as u64);
+        Ok(report)
+    }
+
+    fn migrate_batches<T: Schema>(
+        &self,
+        glob: &Glob,
+        dry_run: bool,
+        bytes: &mut usize,
+    ) -> Result<MigrationReport, Error> {
+        let paths = {
+            let ro = self.env.begin_ro_txn().lmdb(None, pos!())?;
+            query::glob_impl(&ro, self.db, glob).trace_pos(pos!())?
+        };
+
+        let family = describe::<T>();
+        let mut report = MigrationReport::default();
+        for batch in paths.chunks(BATCH_SIZE) {
+            if dry_run {
+                let ro = self.env.begin_ro_txn().lmdb(None, pos!())?;
+                for path in batch {
+                    migrate_node::<T, _>(&ro, self.db, path, &family, &mut report, &self.metrics)
+                        .trace_pos(pos!(path))?;
+                }
+            } else {
+                let mut rw = self.env.begin_rw_txn().lmdb(None, pos!())?;
+                for path in batch {
+                    let converted = migrate_node::<T, _>(
+                        &rw,
+                        self.db,
+                        path,
+                        &family,
+                        &mut report,
+                        &self.metrics,
+                    )
+                    .trace_pos(pos!(path))?;
+                    if let Some(info) = converted {
+                        *bytes += RwTransactionExt::put_unsafe_version(&mut rw, self.db, path, info)
+                            .trace_pos(pos!(path))?;
+                    }
+                }
+                rw.commit().lmdb(None, pos!())?;
+            }
+        }
+        Ok(report)
+    }
+}
+
+/// Converts single node to the `T::version()` and updates report.
+///
+/// Returns new DataWrapper if node should be rewritten.
+fn migrate_node<T: Schema, Txn: lmdb::Transaction>(
+    txn: &Txn,
+    db: lmdb::Database,
+    path: &Path,
+    family: &Chain,
+    report: &mut MigrationReport,
+    metrics: &Metrics,
+) -> Result<Option<DataWrapperV1>, Error> {
+    // Node can be removed between transactions, so just skip it
+    let (info, bytes): (DataWrapperV1, usize) =
+        match RoTransactionExt::info_sized(txn, db, path).trace_pos(pos!())? {
+            None => return Ok(None),
+            Some(info) => info,
+        };
+    metrics.read(bytes);
+
+    *report.versions.entry(info.version).or_insert(0) += 1;
+    if family.get(info.version).is_none() {
+        report.skipped += 1;
+        return Ok(None);
+    }
+    if info.version > T::version() {
+        report.newer.push(path.clone());
+        return Ok(None);
+    }
+    if info.version == T::version() {
+        return Ok(None);
+    }
+
+    let children = info.children.clone();
+    // Versions of simple types overlap with families, so `()` containers are recognized by value
+    let container = info.data.is_nil();
+    let converted =
+        load_node::<T>(path, info, metrics).and_then(|x| x.save().map_err(Error::from));
+    match converted {
+        Ok(data) => {
+            report.migrated += 1;
+            Ok(Some(DataWrapperV1 {
+                children,
+                version: T::version(),
+                data,
+            }))
+        }
+        Err(_) if container => {
+            report.skipped += 1;
+            Ok(None)
+        }
+        Err(e) => {
+            report.failures.push((path.clone(), e));
+            Ok(None)
+        }
+    }
+}
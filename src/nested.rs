@@ -0,0 +1,37 @@
+//! Serde helpers for versioned values inside other serde values.
+//!
+//! By default fields of `SchemaSerdeMarker` types are saved without any version,
+//! so changing type of the field requires new version of the whole struct.
+//! Fields marked with `#[serde(with = "lmtreedb::nested")]` are saved together with their own version
+//! and are upgraded or downgraded independently while loading:
+//!
+//! ```ignore
+//! #[derive(Clone, Debug, Serialize, Deserialize)]
+//! struct User {
+//!     name: String,
+//!     #[serde(with = "lmtreedb::nested")]
+//!     address: Address,
+//! }
+//! ```
+//!
+//! Adding this attribute to the existing field changes its format, so it still requires new version.
+
+use serde::de::Error as _;
+use serde::ser::Error as _;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::*;
+
+/// Saves field using `save_versioned`.
+///
+/// `Schema::save` consumes the value, so it is cloned first.
+pub fn serialize<T: Schema + Clone, S: Serializer>(val: &T, ser: S) -> Result<S::Ok, S::Error> {
+    let val = save_versioned(val.clone()).map_err(S::Error::custom)?;
+    val.serialize(ser)
+}
+
+/// Loads field saved by `serialize`. Does all required upgrades or downgrades.
+pub fn deserialize<'de, T: Schema, D: Deserializer<'de>>(de: D) -> Result<T, D::Error> {
+    let val = rmpv::Value::deserialize(de)?;
+    load_versioned(val).map_err(D::Error::custom)
+}
@@ -1,10 +1,72 @@
 use std::fmt;
 use std::fmt::Display;
+use std::str::FromStr;
+use std::sync::Arc;
+
+use serde::de::{self, Deserialize, Deserializer, SeqAccess, Visitor};
+use serde::{Serialize, Serializer};
+
+pub mod glob;
+pub mod pattern;
+
+/// Name of the default root, see `Root`
+const ROOT: &str = "@root";
 
 /// All pathes in the database are using this type.
-/// Just a simple wrapping around Vec<String> with some handful functions
-#[derive(Clone, Debug)]
-pub struct Path(pub Vec<String>);
+///
+/// Parts are stored in a single buffer together with the LMDB key of the node,
+/// so lookups do not allocate. The key joins parts with slashes, escaping only names with slashes,
+/// so keys of other names are the same as in databases written before escaping was added.
+/// `as_path_ref` and `parent` return borrowed `PathRef` views.
+/// The buffer is shared between clones until one of them is changed.
+///
+/// Pathes are compared part by part, so `a/b` is always less than `a-b` and `a/b/c`.
+#[derive(Clone, Default, PartialEq, Eq, Hash)]
+pub struct Path {
+    inner: Arc<PathBuffer>,
+}
+
+#[derive(Clone, Default, PartialEq, Eq, Hash)]
+struct PathBuffer {
+    /// Parts joined with `/`, see `key_into`
+    key: String,
+    /// End of each part in `key`
+    key_ends: Vec<usize>,
+    /// Parts as is, without separators
+    parts: String,
+    /// End of each part in `parts`
+    ends: Vec<usize>,
+}
+
+/// Borrowed view of `Path` or any of its ancestors
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub struct PathRef<'a> {
+    key: &'a str,
+    key_ends: &'a [usize],
+    parts: &'a str,
+    ends: &'a [usize],
+}
+
+/// Writes part for `Path::parse`, so `\` and `/` inside of it are not mixed up with separators
+fn escape_into(out: &mut String, part: &str) {
+    for c in part.chars() {
+        if c == '/' || c == '\\' {
+            out.push('\\');
+        }
+        out.push(c);
+    }
+}
+
+/// Writes part to the LMDB key. Names are written as is, like keys were always written,
+/// unless they contain `/` or end with `\`, which could be mixed up with an escaped slash.
+/// Such names are escaped, so different pathes never share a key.
+fn key_into(key: &mut String, part: &str) {
+    if part.contains('/') || part.ends_with('\\') {
+        escape_into(key, part);
+    } else {
+        key.push_str(part);
+    }
+}
 
 /// Allows to add any Display'able parts to the path. String, &str and others.
 impl<T: Display> std::ops::Add<T> for Path {
@@ -16,38 +78,406 @@ impl<T: Display> std::ops::Add<T> for Path {
     }
 }
 
+impl<'a> PathRef<'a> {
+    /// Encoded key of the node, the same as `to_string()` unless names contain backslashes.
+    /// LMDB does not allow empty keys, so for the empty path it is `/`.
+    pub fn key(self) -> &'a str {
+        if self.ends.is_empty() {
+            "/"
+        } else {
+            self.key
+        }
+    }
+
+    pub fn len(self) -> usize {
+        self.ends.len()
+    }
+
+    pub fn is_empty(self) -> bool {
+        self.ends.is_empty()
+    }
+
+    /// Part with the specified index
+    pub fn part(self, idx: usize) -> Option<&'a str> {
+        let end = *self.ends.get(idx)?;
+        let start = if idx == 0 { 0 } else { self.ends[idx - 1] };
+        Some(&self.parts[start..end])
+    }
+
+    pub fn components(self) -> impl DoubleEndedIterator<Item = &'a str> + ExactSizeIterator {
+        (0..self.len()).map(move |i| self.part(i).unwrap())
+    }
+
+    /// First `len` parts of the path
+    pub fn prefix(self, len: usize) -> PathRef<'a> {
+        let len = len.min(self.len());
+        let (key_end, end) = match len {
+            0 => (0, 0),
+            _ => (self.key_ends[len - 1], self.ends[len - 1]),
+        };
+        PathRef {
+            key: &self.key[..key_end],
+            key_ends: &self.key_ends[..len],
+            parts: &self.parts[..end],
+            ends: &self.ends[..len],
+        }
+    }
+
+    /// Path without the last part. None if there are no parts.
+    pub fn parent(self) -> Option<PathRef<'a>> {
+        if self.is_empty() {
+            return None;
+        }
+        Some(self.prefix(self.len() - 1))
+    }
+
+    /// Last part of the path
+    pub fn file_name(self) -> Option<&'a str> {
+        self.components().next_back()
+    }
+
+    /// Absolute pathes start from one of the roots, which names always start with `@`
+    pub fn is_absolute(self) -> bool {
+        matches!(self.part(0), Some(x) if x.starts_with('@'))
+    }
+
+    /// Whether all parts of `prefix` are the first parts of this path
+    pub fn starts_with(self, prefix: PathRef) -> bool {
+        prefix.len() <= self.len() && self.prefix(prefix.len()) == prefix
+    }
+
+    pub fn to_path(self) -> Path {
+        Path {
+            inner: Arc::new(PathBuffer {
+                key: self.key.to_string(),
+                key_ends: self.key_ends.to_vec(),
+                parts: self.parts.to_string(),
+                ends: self.ends.to_vec(),
+            }),
+        }
+    }
+}
+
 impl Path {
+    /// Empty relative path
+    pub fn new() -> Path {
+        Path::default()
+    }
+
+    /// Creates path from the parts as is, they are not split by slashes
+    pub fn from_parts<I, S>(parts: I) -> Path
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        let mut res = Path::new();
+        for part in parts {
+            res.push(part.as_ref());
+        }
+        res
+    }
+
+    pub fn as_path_ref(&self) -> PathRef<'_> {
+        PathRef {
+            key: &self.inner.key,
+            key_ends: &self.inner.key_ends,
+            parts: &self.inner.parts,
+            ends: &self.inner.ends,
+        }
+    }
+
+    /// Encoded key of the node, see `PathRef::key`
+    pub fn key(&self) -> &str {
+        self.as_path_ref().key()
+    }
+
+    pub fn len(&self) -> usize {
+        self.inner.ends.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.inner.ends.is_empty()
+    }
+
+    /// Adds single part as is, without splitting it by slashes
+    pub fn push(&mut self, part: &str) {
+        let inner = Arc::make_mut(&mut self.inner);
+        if !inner.ends.is_empty() {
+            inner.key.push('/');
+        }
+        key_into(&mut inner.key, part);
+        inner.key_ends.push(inner.key.len());
+        inner.parts.push_str(part);
+        inner.ends.push(inner.parts.len());
+    }
+
+    /// Keeps only first `len` parts
+    pub fn truncate(&mut self, len: usize) {
+        let prefix = self.as_path_ref().prefix(len);
+        let (key_end, end) = (prefix.key.len(), prefix.parts.len());
+        let inner = Arc::make_mut(&mut self.inner);
+        inner.key.truncate(key_end);
+        inner.key_ends.truncate(len);
+        inner.parts.truncate(end);
+        inner.ends.truncate(len);
+    }
+
     /// Splits this path to the parent path and name.
     /// If it does not contain any parts, None is returned as name.
     pub fn pop(&self) -> (Self, Option<String>) {
+        match self.as_path_ref().parent() {
+            None => (self.clone(), None),
+            Some(parent) => (parent.to_path(), self.file_name().map(|x| x.to_string())),
+        }
+    }
+
+    /// Parses path from the text.
+    ///
+    /// Path is absolute if it starts with a root name like `@root` or `@system`, otherwise it is relative.
+    /// Leading `/` means the default root, unless a root name follows it.
+    /// Empty parts and `.` are skipped, `..` removes the previous part, but never goes above the root.
+    /// Leading `..` of relative path are kept to be resolved by `join`.
+    ///
+    /// `\/` is a slash inside the name and `\\` is a backslash, other backslashes are kept as is.
+    /// Names `.` and `..` cannot be written at all.
+    /// ```
+    /// use lmtreedb::path::*;
+    ///
+    /// assert_eq!(Path::parse("/a/./b/../c").to_string(), "@root/a/c");
+    /// let parts = |s: &str| Path::parse(s).components().map(String::from).collect::<Vec<_>>();
+    /// assert_eq!(parts("@root/a\\/b"), vec!["@root", "a/b"]);
+    /// assert_eq!(parts("../a"), vec!["..", "a"]);
+    /// ```
+    pub fn parse(s: &str) -> Path {
+        let mut res = Path::new();
+        let rest = match s.strip_prefix('/') {
+            // Both `/@root/a` and `@root/a` are the same absolute path
+            Some(rest) if rest.starts_with('@') => rest,
+            Some(rest) => {
+                res.push(ROOT);
+                rest
+            }
+            None => s,
+        };
+
+        let mut name = String::new();
+        let mut chars = rest.chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                '\\' if matches!(chars.peek(), Some(&'/') | Some(&'\\')) => {
+                    name.extend(chars.next());
+                }
+                '/' => {
+                    res.push_part(&name);
+                    name.clear();
+                }
+                c => name.push(c),
+            }
+        }
+        res.push_part(&name);
+        res
+    }
+
+    /// Adds single part, resolving `.` and `..`
+    fn push_part(&mut self, name: &str) {
+        match name {
+            "" | "." => {}
+            ".." => match self.file_name() {
+                None | Some("..") => self.push(name),
+                Some(_) if self.len() == 1 && self.is_absolute() => {}
+                Some(_) => self.truncate(self.len() - 1),
+            },
+            _ => self.push(name),
+        }
+    }
+
+    /// Absolute pathes start from the root node
+    pub fn is_absolute(&self) -> bool {
+        self.as_path_ref().is_absolute()
+    }
+
+    /// Path without the last part. None if there are no parts.
+    pub fn parent(&self) -> Option<PathRef<'_>> {
+        self.as_path_ref().parent()
+    }
+
+    /// Last part of the path
+    pub fn file_name(&self) -> Option<&str> {
+        self.as_path_ref().file_name()
+    }
+
+    /// Whether all parts of `prefix` are the first parts of this path
+    pub fn starts_with(&self, prefix: &Path) -> bool {
+        self.as_path_ref().starts_with(prefix.as_path_ref())
+    }
+
+    /// Relative path that gives this one when joined to `prefix`
+    pub fn strip_prefix(&self, prefix: &Path) -> Option<Path> {
+        if !self.starts_with(prefix) {
+            return None;
+        }
+        Some(Path::from_parts(self.components().skip(prefix.len())))
+    }
+
+    /// Appends `other` to this path, resolving its `..` parts. If `other` is absolute, it is returned as is.
+    pub fn join(&self, other: &Path) -> Path {
+        if other.is_absolute() {
+            return other.clone();
+        }
         let mut res = self.clone();
-        let x = res.0.pop();
-        (res, x)
+        for part in other.components() {
+            res.push_part(part);
+        }
+        res
+    }
+
+    pub fn components(&self) -> impl DoubleEndedIterator<Item = &str> + ExactSizeIterator {
+        self.as_path_ref().components()
+    }
+}
+
+impl<'a> From<PathRef<'a>> for Path {
+    fn from(path: PathRef<'a>) -> Self {
+        path.to_path()
+    }
+}
+
+impl FromStr for Path {
+    type Err = std::convert::Infallible;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(Path::parse(s))
     }
 }
 
-/// Same as std::ops::Add, but inplace
+/// Same as std::ops::Add, but inplace. Slashes in `rhs` split it to several parts.
 impl<T: Display> std::ops::AddAssign<T> for Path {
     fn add_assign(&mut self, rhs: T) {
-        let s = rhs.to_string();
-        for i in s.split('/') {
-            // TODO: Bypass .to_string() call
-            self.0.push(i.to_string());
+        for part in rhs.to_string().split('/') {
+            self.push(part);
         }
     }
 }
 
-/// Converts path display, using slash as separator
+impl Ord for Path {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        self.as_path_ref().cmp(&other.as_path_ref())
+    }
+}
+
+impl PartialOrd for Path {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<'a> Ord for PathRef<'a> {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        self.components().cmp(other.components())
+    }
+}
+
+impl<'a> PartialOrd for PathRef<'a> {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// Converts path display, using slash as separator. `Path::parse` gives the same path back.
 impl fmt::Display for Path {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        let mut res: String = self.0.join("/");
-        if res.is_empty() {
-            res = "/".to_string()
+        fmt::Display::fmt(&self.as_path_ref(), f)
+    }
+}
+
+impl<'a> fmt::Display for PathRef<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        // Without backslashes no name is escaped, so the key is the same text
+        if !self.key.contains('\\') {
+            return f.write_str(self.key);
+        }
+        let mut res = String::with_capacity(self.key.len());
+        for (i, part) in self.components().enumerate() {
+            if i > 0 {
+                res.push('/');
+            }
+            escape_into(&mut res, part);
+        }
+        f.write_str(&res)
+    }
+}
+
+impl fmt::Debug for Path {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(&self.as_path_ref(), f)
+    }
+}
+
+impl<'a> fmt::Debug for PathRef<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_list().entries(self.components()).finish()
+    }
+}
+
+/// Serialized as the sequence of parts, so names with slashes are kept as is
+impl Serialize for Path {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(self.components())
+    }
+}
+
+/// Human readable formats also accept strings, which are parsed with `Path::parse`
+impl<'de> Deserialize<'de> for Path {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct PathVisitor;
+
+        impl<'de> Visitor<'de> for PathVisitor {
+            type Value = Path;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                write!(f, "sequence of path parts or path string")
+            }
+
+            fn visit_str<E: de::Error>(self, v: &str) -> Result<Path, E> {
+                Ok(Path::parse(v))
+            }
+
+            fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Path, A::Error> {
+                let mut res = Path::new();
+                while let Some(part) = seq.next_element::<String>()? {
+                    res.push(&part);
+                }
+                Ok(res)
+            }
+        }
+
+        if deserializer.is_human_readable() {
+            deserializer.deserialize_any(PathVisitor)
+        } else {
+            deserializer.deserialize_seq(PathVisitor)
         }
-        write!(f, "{}", res)
     }
 }
 
+/// Deserializes typed part from its full path, checking the last part with `FromPath::from_parent`.
+/// All parts are serialized as their full path.
+pub fn deserialize_part<'de, D, T>(deserializer: D) -> Result<T, D::Error>
+where
+    D: Deserializer<'de>,
+    T: FromPath,
+{
+    let path = Path::deserialize(deserializer)?;
+    let (parent, name) = path.pop();
+    name.and_then(|name| T::from_parent(parent, &name))
+        .ok_or_else(|| {
+            de::Error::custom(format!(
+                "'{}' is not a valid {}",
+                path,
+                std::intrinsics::type_name::<T>()
+            ))
+        })
+}
+
 /// Marker trait that specifies possible parent for node
 /// ```
 /// use lmtreedb::path::*;
@@ -164,12 +594,75 @@ pub trait PathPart: Sized {
     }
 }
 
-/// The root node of database.
+/// Path parts that can be recognized in the concrete path. Allows to build `pattern::Pattern` from them.
+///
+/// Implemented by `path!` for the new parts, `DynPath` and `Pathify`.
+pub trait FromPath: PathPart {
+    /// Pattern segment for the part this type adds to its parent
+    fn segment() -> pattern::Segment;
+
+    /// Creates part from the parent path and the last part name, if the name is valid for this type
+    fn from_parent(parent: Path, name: &str) -> Option<Self>;
+}
+
+/// Path part that is bound to the type of value stored at it.
+/// Allows to use `Storage::get_typed` and `Storage::put_typed` without specifying the value type.
+/// ```
+/// use lmtreedb::path::*;
+///
+/// struct Counter(Path);
+/// impl PathPart for Counter {
+///     fn path(self) -> Path {self.0}
+/// }
+///
+/// impl TypedPath for Counter {
+///     type Value = u64;
+/// }
+/// ```
+pub trait TypedPath: PathPart {
+    type Value: crate::schema::Schema;
+}
+
+/// One of the top-level nodes of database. Default one is `@root`.
+///
+/// Roots are created on demand, when something is put inside of them. See `Storage::roots`.
+/// Use `path!(root MyRoot = "@my")` to declare strictly typed root.
 pub struct Root(Path);
 
+impl Root {
+    /// Root with the specified name. `@` is added if name does not start with it.
+    pub fn named(name: &str) -> Root {
+        let mut path = Path::new();
+        if name.starts_with('@') {
+            path.push(name);
+        } else {
+            path.push(&format!("@{}", name));
+        }
+        Root(path)
+    }
+
+    /// Name of root, including `@`
+    pub fn name(&self) -> &str {
+        self.0.key()
+    }
+}
+
 impl Default for Root {
     fn default() -> Self {
-        Self(Path(vec!["@root".to_string()]))
+        Root::named(ROOT)
+    }
+}
+
+impl FromPath for Root {
+    fn segment() -> pattern::Segment {
+        pattern::Segment::Param(String::new())
+    }
+
+    fn from_parent(parent: Path, name: &str) -> Option<Self> {
+        if !parent.is_empty() || !name.starts_with('@') {
+            return None;
+        }
+        Some(Root::named(name))
     }
 }
 
@@ -179,6 +672,18 @@ impl PathPart for Root {
     }
 }
 
+impl Serialize for Root {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.0.serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for Root {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserialize_part(deserializer)
+    }
+}
+
 /// Path part that just extends parent with any Display'able type. Not really strict, but useful
 pub struct DynPath(Path);
 
@@ -194,6 +699,29 @@ impl PathPart for DynPath {
     }
 }
 
+impl Serialize for DynPath {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.0.serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for DynPath {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserialize_part(deserializer)
+    }
+}
+
+impl FromPath for DynPath {
+    fn segment() -> pattern::Segment {
+        pattern::Segment::Param(String::new())
+    }
+
+    fn from_parent(mut parent: Path, name: &str) -> Option<Self> {
+        parent.push(name);
+        Some(Self(parent))
+    }
+}
+
 /// Path part that wraps provided type. Like DynPath, but much more strict
 pub struct Pathify<T: Display> {
     parent: Path,
@@ -215,6 +743,29 @@ impl<T: Display> PathPart for Pathify<T> {
     }
 }
 
+impl<T: Display> Serialize for Pathify<T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        (self.parent.clone() + &self.value).serialize(serializer)
+    }
+}
+
+impl<'de, T: Display + FromStr> Deserialize<'de> for Pathify<T> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserialize_part(deserializer)
+    }
+}
+
+impl<T: Display + FromStr> FromPath for Pathify<T> {
+    fn segment() -> pattern::Segment {
+        pattern::Segment::Param(String::new())
+    }
+
+    fn from_parent(parent: Path, name: &str) -> Option<Self> {
+        let value = name.parse().ok()?;
+        Some(Self { parent, value })
+    }
+}
+
 /// Macro to make strict pathes a lot easier to use
 ///
 /// # Create new part
@@ -227,18 +778,41 @@ impl<T: Display> PathPart for Pathify<T> {
 /// `path!(pub MyPart = "my_part")` or `path!(pub def MyPart)`
 /// Just adds pub modifier to result structs.
 ///
+/// `path!(MyPart = "my_part" => MyValue)` or `path!(def MyPart => MyValue)`
+/// Also binds part to the type of stored value, see `TypedPath`.
+///
+/// `path!(root MyRoot = "@my")`
+/// Creates unit struct for the custom root, it can be used instead of `Root` everywhere: `path!([MyRoot] / A)`.
+///
 /// # Combine pathes
 /// Always first argument must be expression (usually Root) and in square braces.
 /// After it one or more parts can be specified.
 /// If part requires an argument, it must be supplied in square braces right after the identifier of part
 /// All parts are separated using `/`
 ///
+/// # Build pattern
+/// `path!(pattern [root] / A / B)` creates `pattern::Pattern` from the parts implementing `FromPath`.
+/// Parts with values (`DynPath`, `Pathify`) become unnamed parameters. Parts must be single tokens,
+/// so use type aliases for generic parts.
+///
 /// # Specify links
 /// This macro also allows to easly specify what parts can be after other.
 ///
 /// See `mod tests` for examples.
 #[macro_export]
 macro_rules! path {
+    // Pattern: path!(pattern [root] / A / B)
+    (pattern [$($root:tt)*] $(/ $t:tt)+) => {
+        {
+            let root = $crate::path::PathPart::path($crate::path!(@root $($root)*));
+            let mut pattern = $crate::path::pattern::Pattern::from_path(&root);
+            $(
+                // Unnamed parameters never clash
+                let _ = pattern.push(<$t as $crate::path::FromPath>::segment());
+            )+
+            pattern
+        }
+    };
     // Combine path
     // Simple: path!([root] / T)
     ([$($root:tt)*] / $t:ty) => {
@@ -269,10 +843,60 @@ macro_rules! path {
     };
 
     // Create new
+    ($vis:vis $id:ident = $name:expr => $value:ty) => {
+        $crate::path!($vis $id = $name);
+        impl $crate::path::TypedPath for $id {
+            type Value = $value;
+        }
+    };
     ($vis:vis $id:ident = $name:expr) => {
         $vis struct $id($crate::path::Path);
         $crate::path!(@impl $id $name);
     };
+    // Custom root: path!(root MyRoot = "@my")
+    (root $vis:vis $id:ident = $name:expr) => {
+        #[derive(Clone, Copy, Debug, Default)]
+        $vis struct $id;
+        impl $crate::path::PathPart for $id {
+            fn path(self) -> $crate::path::Path {
+                $crate::path::PathPart::path($crate::path::Root::named($name))
+            }
+        }
+        impl $crate::path::FromPath for $id {
+            fn segment() -> $crate::path::pattern::Segment {
+                let root = $crate::path::Root::named($name);
+                $crate::path::pattern::Segment::Literal(root.name().to_string())
+            }
+            fn from_parent(parent: $crate::path::Path, name: &str) -> Option<Self> {
+                let root = $crate::path::Root::named($name);
+                if parent.is_empty() && name == root.name() {
+                    Some(Self)
+                } else {
+                    None
+                }
+            }
+        }
+        impl $crate::schema::serde::Serialize for $id {
+            fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+            where
+                S: $crate::schema::serde::Serializer,
+            {
+                let path = $crate::path::PathPart::path(*self);
+                $crate::schema::serde::Serialize::serialize(&path, serializer)
+            }
+        }
+        impl<'de> $crate::schema::serde::Deserialize<'de> for $id {
+            fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+            where
+                D: $crate::schema::serde::Deserializer<'de>,
+            {
+                $crate::path::deserialize_part(deserializer)
+            }
+        }
+    };
+    (def $vis:vis $name:ident => $value:ty) => {
+        $crate::path!($vis $name = stringify!($name) => $value);
+    };
     (def $vis:vis $name:ident) => {
         $crate::path!($vis $name = stringify!($name) );
     };
@@ -288,6 +912,34 @@ macro_rules! path {
                 self.0
             }
         }
+        impl $crate::path::FromPath for $id {
+            fn segment() -> $crate::path::pattern::Segment {
+                $crate::path::pattern::Segment::Literal(($name).to_string())
+            }
+            fn from_parent(parent: $crate::path::Path, name: &str) -> Option<Self> {
+                if name == $name {
+                    Some(Self(parent + name))
+                } else {
+                    None
+                }
+            }
+        }
+        impl $crate::schema::serde::Serialize for $id {
+            fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+            where
+                S: $crate::schema::serde::Serializer,
+            {
+                $crate::schema::serde::Serialize::serialize(&self.0, serializer)
+            }
+        }
+        impl<'de> $crate::schema::serde::Deserialize<'de> for $id {
+            fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+            where
+                D: $crate::schema::serde::Deserializer<'de>,
+            {
+                $crate::path::deserialize_part(deserializer)
+            }
+        }
     };
 
     // Link
@@ -324,8 +976,13 @@ mod test {
     path!(A = "a");
     path!(B = "b");
     path!(def C);
+    path!(D = "d" => u32);
     path!(Root -> A -> B);
     path!(* -> C -> DynPath -> A);
+    path!(root System = "@system");
+    path!(System -> A);
+    type Num = Pathify<u32>;
+    path!(C -> Num);
 
     #[test]
     fn test_root() {
@@ -372,6 +1029,142 @@ mod test {
         assert_eq!(n.into_string(), "@root/a/C/123/a/C/345");
     }
 
+    #[test]
+    fn test_typed() {
+        fn value_of<P: TypedPath>(_: &P) -> &'static str {
+            std::any::type_name::<P::Value>()
+        }
+        let d = D::extend(Root::default().path());
+        assert_eq!(value_of(&d), "u32");
+        assert_eq!(d.into_string(), "@root/d");
+    }
+
+    #[test]
+    fn test_parse() {
+        let p = |s: &str| -> Vec<String> { Path::parse(s).components().map(String::from).collect() };
+        assert_eq!(p("/"), vec!["@root"]);
+        assert_eq!(p("/a//b/"), vec!["@root", "a", "b"]);
+        assert_eq!(p("/@root/a"), vec!["@root", "a"]);
+        assert_eq!(p("@root/../.."), vec!["@root"]);
+        assert_eq!(p("a/../../b"), vec!["..", "b"]);
+        assert_eq!(p("a\\/b/c\\\\/d\\e"), vec!["a/b", "c\\", "d\\e"]);
+        assert_eq!(p(""), Vec::<String>::new());
+
+        let root = Root::default().path();
+        let a = root.clone() + "a";
+        assert_eq!(Path::parse(&a.to_string()), a);
+        assert_eq!("/a".parse::<Path>().unwrap(), a);
+        assert_eq!(Path::parse(&Path::new().to_string()), Path::new());
+
+        // Displayed names are escaped, so they are parsed back as is
+        let names = Path::from_parts(["@root", "a/b", "c\\", "d\\e"]);
+        assert_eq!(names.to_string(), "@root/a\\/b/c\\\\/d\\\\e");
+        assert_eq!(Path::parse(&names.to_string()), names);
+        assert_ne!(names.key(), Path::parse("/a/b/c/d\\e").key());
+        // Keys of names without slashes are not escaped, like before
+        assert_eq!(names.key(), "@root/a\\/b/c\\\\/d\\e");
+        let plain = Path::from_parts(["@root", "d\\e", "f\\\\g"]);
+        assert_eq!(plain.key(), ["@root", "d\\e", "f\\\\g"].join("/"));
+        assert_eq!(Path::parse(&plain.to_string()), plain);
+        // Trailing backslash would be mixed up with an escaped slash
+        assert_ne!(Path::from_parts(["c\\", "d"]).key(), Path::from_parts(["c/d"]).key());
+        assert!(Path::parse("/").is_absolute());
+        assert!(!Path::parse("a").is_absolute());
+    }
+
+    #[test]
+    fn test_relations() {
+        let root = Root::default().path();
+        let ab = root.clone() + "a/b";
+        assert_eq!(ab.parent().map(Path::from), Some(root.clone() + "a"));
+        assert_eq!(Path::new().parent(), None);
+        assert_eq!(ab.file_name(), Some("b"));
+        assert!(ab.starts_with(&(root.clone() + "a")));
+        assert!(!ab.starts_with(&(root.clone() + "b")));
+        assert_eq!(ab.strip_prefix(&root), Some(Path::parse("a/b")));
+        assert_eq!(ab.components().collect::<Vec<_>>(), vec!["@root", "a", "b"]);
+
+        assert_eq!(ab.join(&Path::parse("../c")), root.clone() + "a/c");
+        assert_eq!(ab.join(&Path::parse("../../../..")), root);
+        assert_eq!(ab.join(&Path::parse("/d")), root.clone() + "d");
+
+        // Compared part by part, not as strings
+        assert!(root.clone() + "a/b" < root.clone() + "a-b");
+        let set: std::collections::BTreeSet<Path> =
+            vec![ab.clone(), root.clone(), ab].into_iter().collect();
+        assert_eq!(set.len(), 2);
+    }
+
+    #[test]
+    fn test_repr() {
+        let mut p = Path::new() + "a/b" + 1;
+        assert_eq!(p.components().collect::<Vec<_>>(), vec!["a", "b", "1"]);
+        assert_eq!(p.key(), "a/b/1");
+        assert_eq!(Path::new().key(), "/");
+
+        let shared = p.clone();
+        p.push("c/d");
+        assert_eq!(p.len(), 4);
+        assert_eq!(p.file_name(), Some("c/d"));
+        // The key is escaped, so it differs from the key of `a/b/1/c/d`
+        assert_eq!(p.key(), "a/b/1/c\\/d");
+        assert_ne!(p.key(), (shared.clone() + "c/d").key());
+        assert_eq!(shared.key(), "a/b/1");
+
+        let parent = p.parent().unwrap();
+        assert_eq!(parent.key(), "a/b/1");
+        assert_eq!(parent.parent().unwrap().file_name(), Some("b"));
+        assert_eq!(Path::from(parent), shared);
+        p.truncate(1);
+        assert_eq!(p.key(), "a");
+    }
+
+    #[test]
+    fn test_serde() {
+        let path = Path::parse("/a/b\\/c");
+        let val = rmpv::ext::to_value(&path).unwrap();
+        let parts = vec!["@root", "a", "b/c"];
+        assert_eq!(val, rmpv::Value::Array(parts.into_iter().map(Into::into).collect()));
+        assert_eq!(rmpv::ext::from_value::<Path>(val).unwrap(), path);
+        let val = rmpv::Value::from("/a/b");
+        assert_eq!(rmpv::ext::from_value::<Path>(val).unwrap(), Path::parse("/a/b"));
+
+        let n = path!([] / A / C / Num[123]);
+        let val = rmpv::ext::to_value(&n).unwrap();
+        let n: Num = rmpv::ext::from_value(val.clone()).unwrap();
+        assert_eq!(n.value, 123);
+        let c: DynPath = rmpv::ext::from_value(val.clone()).unwrap();
+        assert_eq!(c.into_string(), "@root/a/C/123");
+        assert!(rmpv::ext::from_value::<A>(val).is_err());
+
+        let a: A = Root::default().child();
+        let val = rmpv::ext::to_value(&a).unwrap();
+        let a: A = rmpv::ext::from_value(val).unwrap();
+        assert_eq!(a.into_string(), "@root/a");
+
+        let root: Root = rmpv::ext::from_value(rmpv::Value::from("/")).unwrap();
+        assert_eq!(root.into_string(), "@root");
+    }
+
+    #[test]
+    fn test_custom_root() {
+        assert_eq!(Root::named("user").name(), "@user");
+        assert_eq!(Root::named("@user").path(), Path::parse("@user"));
+
+        let a = path!([System] / A);
+        assert_eq!(a.into_string(), "@system/a");
+        assert_eq!(Path::parse("/@system/../a"), Path::parse("@system/a"));
+        assert!(Path::parse("@system/a").is_absolute());
+
+        let pattern = path!(pattern [System] / A);
+        assert_eq!(pattern.to_string(), "@system/a");
+        let path = Path::parse("@system");
+        assert!(pattern::Pattern::from_path(&path).matches_typed::<System>(&path).is_some());
+        let val = rmpv::ext::to_value(System).unwrap();
+        assert!(rmpv::ext::from_value::<System>(val.clone()).is_ok());
+        assert_eq!(rmpv::ext::from_value::<Root>(val).unwrap().name(), "@system");
+    }
+
     #[test]
     fn test_optional_root() {
         let n = path!([] / A);
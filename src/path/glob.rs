@@ -0,0 +1,240 @@
+//! Glob patterns for pathes, see `Storage::glob`.
+//!
+//! Pattern is split to parts like `Path::parse` does. Inside of a part:
+//! - `*` matches any sequence of characters
+//! - `?` matches any single character
+//! - `[abc]`, `[a-z]` and `[!a-z]` match single character from the class
+//! - `\` escapes the next character
+//!
+//! Part `**` matches any number of parts, including zero. Relative patterns are matched from the root.
+
+use my_error::*;
+
+use super::{Path, PathPart, Root};
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Token {
+    Char(char),
+    Any,
+    AnyChar,
+    Class {
+        negated: bool,
+        ranges: Vec<(char, char)>,
+    },
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Segment {
+    Literal(String),
+    Wildcard(Vec<Token>),
+    /// `**`
+    Recursive,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Glob {
+    segments: Vec<Segment>,
+}
+
+impl Glob {
+    pub fn parse(s: &str) -> Result<Glob, Error> {
+        let path = Root::default().path().join(&Path::parse(s));
+        let segments: Result<Vec<Segment>, Error> =
+            path.components().map(parse_segment).collect();
+        Ok(Glob {
+            segments: segments.epos(pos!(quiet s))?,
+        })
+    }
+
+    /// Longest path without wildcards at the start of pattern. All matching pathes are inside of it.
+    pub fn prefix(&self) -> Path {
+        let parts = self.segments.iter().map_while(|x| match x {
+            Segment::Literal(lit) => Some(lit.clone()),
+            Segment::Wildcard(_) | Segment::Recursive => None,
+        });
+        Path::from_parts(parts)
+    }
+
+    pub fn matches(&self, path: &Path) -> bool {
+        let mut state = self.start();
+        for part in path.components() {
+            state = self.advance(&state, part);
+            if state.is_empty() {
+                return false;
+            }
+        }
+        self.is_match(&state)
+    }
+
+    /// Indexes of segments that can match the next part. Used to walk the tree part by part.
+    pub(crate) fn start(&self) -> Vec<usize> {
+        let mut res = Vec::new();
+        self.add_state(&mut res, 0);
+        res
+    }
+
+    /// Moves all states through the next part. Empty result means nothing below can match.
+    pub(crate) fn advance(&self, state: &[usize], part: &str) -> Vec<usize> {
+        let mut res = Vec::new();
+        for &idx in state {
+            match self.segments.get(idx) {
+                Some(Segment::Recursive) => self.add_state(&mut res, idx),
+                Some(Segment::Literal(lit)) if lit == part => self.add_state(&mut res, idx + 1),
+                Some(Segment::Wildcard(tokens)) if match_tokens(tokens, part) => {
+                    self.add_state(&mut res, idx + 1)
+                }
+                // Whole pattern is already matched
+                None => {}
+                Some(Segment::Literal(_)) | Some(Segment::Wildcard(_)) => {}
+            }
+        }
+        res
+    }
+
+    pub(crate) fn is_match(&self, state: &[usize]) -> bool {
+        state.contains(&self.segments.len())
+    }
+
+    /// Adds state with all states reachable by skipping `**`
+    fn add_state(&self, state: &mut Vec<usize>, idx: usize) {
+        if state.contains(&idx) {
+            return;
+        }
+        state.push(idx);
+        if let Some(Segment::Recursive) = self.segments.get(idx) {
+            self.add_state(state, idx + 1);
+        }
+    }
+}
+
+fn parse_segment(part: &str) -> Result<Segment, Error> {
+    if part == "**" {
+        return Ok(Segment::Recursive);
+    }
+
+    let mut tokens = Vec::new();
+    let mut chars = part.chars();
+    while let Some(c) = chars.next() {
+        let token = match c {
+            '*' => Token::Any,
+            '?' => Token::AnyChar,
+            '\\' => {
+                let c = chars.next();
+                Token::Char(c.err_msg(pos!(), msg!("Dangling escape in '{}'", part))?)
+            }
+            '[' => {
+                let mut class: Vec<char> = Vec::new();
+                loop {
+                    match chars.next() {
+                        // `]` right after `[` or `[!` is a part of the class
+                        Some(']') if !class.is_empty() && class != ['!'] => break,
+                        Some(c) => class.push(c),
+                        None => return Err(err!("Unclosed character class in '{}'", part)),
+                    }
+                }
+                let negated = class[0] == '!';
+                if negated {
+                    class.remove(0);
+                }
+                let mut ranges = Vec::new();
+                let mut i = 0;
+                while i < class.len() {
+                    if i + 2 < class.len() && class[i + 1] == '-' {
+                        ranges.push((class[i], class[i + 2]));
+                        i += 3;
+                    } else {
+                        ranges.push((class[i], class[i]));
+                        i += 1;
+                    }
+                }
+                Token::Class { negated, ranges }
+            }
+            c => Token::Char(c),
+        };
+        tokens.push(token);
+    }
+
+    if tokens.iter().all(|x| matches!(x, Token::Char(_))) {
+        let lit = tokens
+            .into_iter()
+            .map(|x| match x {
+                Token::Char(c) => c,
+                _ => unreachable!(),
+            })
+            .collect();
+        return Ok(Segment::Literal(lit));
+    }
+    Ok(Segment::Wildcard(tokens))
+}
+
+fn match_tokens(tokens: &[Token], s: &str) -> bool {
+    match tokens.split_first() {
+        None => s.is_empty(),
+        Some((Token::Any, rest)) => s
+            .char_indices()
+            .map(|(i, _)| i)
+            .chain(std::iter::once(s.len()))
+            .any(|i| match_tokens(rest, &s[i..])),
+        Some((token, rest)) => {
+            let mut chars = s.chars();
+            let c = match chars.next() {
+                None => return false,
+                Some(c) => c,
+            };
+            let ok = match token {
+                Token::Char(x) => *x == c,
+                Token::AnyChar => true,
+                Token::Class { negated, ranges } => {
+                    ranges.iter().any(|&(from, to)| from <= c && c <= to) != *negated
+                }
+                Token::Any => unreachable!(),
+            };
+            ok && match_tokens(rest, chars.as_str())
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn matches(glob: &str, path: &str) -> bool {
+        Glob::parse(glob).unwrap().matches(&Path::parse(path))
+    }
+
+    #[test]
+    fn test_parts() {
+        assert!(matches("users/*/name", "/users/42/name"));
+        assert!(!matches("users/*/name", "/users/42/x/name"));
+        assert!(matches("users/a*c", "/users/abbc"));
+        assert!(matches("users/a*c", "/users/ac"));
+        assert!(!matches("users/a*c", "/users/acb"));
+        assert!(matches("users/?", "/users/1"));
+        assert!(!matches("users/?", "/users/12"));
+        assert!(matches("users/[0-9x]", "/users/x"));
+        assert!(!matches("users/[!0-9]", "/users/5"));
+        assert!(matches("users/[]]", "/users/]"));
+        assert!(matches("users/\\*", "/users/*"));
+        assert!(!matches("users/\\*", "/users/a"));
+        assert!(Glob::parse("users/[a").is_err());
+    }
+
+    #[test]
+    fn test_recursive() {
+        assert!(matches("users/**", "/users"));
+        assert!(matches("users/**", "/users/1/2/3"));
+        assert!(matches("**/name", "/name"));
+        assert!(matches("**/name", "/users/1/name"));
+        assert!(!matches("**/name", "/users/1/name/x"));
+        assert!(matches("users/**/s/**", "/users/1/s"));
+        assert!(matches("users/**/s/**", "/users/1/s/2/s/3"));
+    }
+
+    #[test]
+    fn test_prefix() {
+        let glob = Glob::parse("/users/*/sessions/**").unwrap();
+        assert_eq!(glob.prefix(), Path::parse("/users"));
+        let glob = Glob::parse("/**").unwrap();
+        assert_eq!(glob.prefix(), Root::default().path());
+    }
+}
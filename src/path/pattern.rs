@@ -0,0 +1,246 @@
+//! Reverse routing: matching concrete pathes against patterns.
+//!
+//! Patterns can be written as text, where `{name}` matches any single part:
+//! ```ignore
+//! let posts = Pattern::parse("users/{id}/posts")?;
+//! if let Some(caps) = posts.matches(&path) {
+//!     let id: u64 = caps.parse("id")?;
+//! }
+//! ```
+//!
+//! Or built from the same types that are used with `path!`, see `FromPath`:
+//! ```ignore
+//! let posts = path!(pattern [] / Users / UserId / Posts);
+//! if let Some((posts, caps)) = posts.matches_typed::<Posts>(&path) { ... }
+//! ```
+
+use std::fmt;
+use std::str::FromStr;
+
+use my_error::*;
+
+use super::{FromPath, Path, PathPart, Root};
+
+/// Single part of the pattern
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Segment {
+    /// Matches only the same part
+    Literal(String),
+    /// Matches any part. Name may be empty, then parameter is accessible only by index.
+    Param(String),
+}
+
+/// Sequence of segments that matches pathes with the same number of parts
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Pattern {
+    segments: Vec<Segment>,
+}
+
+impl Pattern {
+    /// Parses pattern using the same rules as `Path::parse`. Parts like `{name}` are parameters.
+    /// Relative patterns are matched from the root.
+    pub fn parse(s: &str) -> Result<Pattern, Error> {
+        let path = Root::default().path().join(&Path::parse(s));
+        let mut res = Pattern::from_path(&Path::new());
+        for part in path.components() {
+            let segment = match part.strip_prefix('{').and_then(|x| x.strip_suffix('}')) {
+                Some(name) => Segment::Param(name.to_string()),
+                None => Segment::Literal(part.to_string()),
+            };
+            res.push(segment).epos(pos!(quiet s))?;
+        }
+        Ok(res)
+    }
+
+    /// Pattern that matches only the given path
+    pub fn from_path(path: &Path) -> Pattern {
+        Pattern {
+            segments: path.components().map(|x| Segment::Literal(x.to_string())).collect(),
+        }
+    }
+
+    /// Adds segment to the end. Fails if parameter with the same name already exists
+    pub fn push(&mut self, segment: Segment) -> Result<(), Error> {
+        if let Segment::Param(name) = &segment {
+            if !name.is_empty() && self.params().any(|x| x == name) {
+                return Err(err!("Duplicate parameter '{}'", name));
+            }
+        }
+        self.segments.push(segment);
+        Ok(())
+    }
+
+    pub fn segments(&self) -> &[Segment] {
+        &self.segments
+    }
+
+    /// Names of all parameters in order
+    pub fn params(&self) -> impl Iterator<Item = &str> {
+        self.segments.iter().filter_map(|x| match x {
+            Segment::Param(name) => Some(name.as_str()),
+            Segment::Literal(_) => None,
+        })
+    }
+
+    /// Returns values of all parameters if `path` matches this pattern
+    pub fn matches(&self, path: &Path) -> Option<Captures> {
+        if path.len() != self.segments.len() {
+            return None;
+        }
+        let mut params = Vec::new();
+        for (segment, part) in self.segments.iter().zip(path.components()) {
+            match segment {
+                Segment::Literal(lit) if lit == part => {}
+                Segment::Literal(_) => return None,
+                Segment::Param(name) => params.push((name.clone(), part.to_string())),
+            }
+        }
+        Some(Captures { params })
+    }
+
+    /// Same as `matches`, but also builds the typed path part from the last part of `path`
+    pub fn matches_typed<T: FromPath>(&self, path: &Path) -> Option<(T, Captures)> {
+        let caps = self.matches(path)?;
+        let (parent, name) = path.pop();
+        let part = T::from_parent(parent, &name?)?;
+        Some((part, caps))
+    }
+}
+
+/// Renders pattern back to the text accepted by `Pattern::parse`
+impl fmt::Display for Pattern {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.segments.is_empty() {
+            return write!(f, "/");
+        }
+        for (i, segment) in self.segments.iter().enumerate() {
+            if i > 0 {
+                write!(f, "/")?;
+            }
+            match segment {
+                Segment::Literal(lit) => {
+                    let mut res = String::with_capacity(lit.len());
+                    super::escape_into(&mut res, lit);
+                    f.write_str(&res)?
+                }
+                Segment::Param(name) => write!(f, "{{{}}}", name)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for Pattern {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Pattern::parse(s)
+    }
+}
+
+/// Values of parameters extracted by `Pattern::matches`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Captures {
+    params: Vec<(String, String)>,
+}
+
+impl Captures {
+    /// Value of the named parameter
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.params
+            .iter()
+            .find(|(x, _)| x == name)
+            .map(|(_, val)| val.as_str())
+    }
+
+    /// Value of parameter by its index, works for unnamed ones too
+    pub fn nth(&self, idx: usize) -> Option<&str> {
+        self.params.get(idx).map(|(_, val)| val.as_str())
+    }
+
+    /// Parses value of the named parameter
+    pub fn parse<T>(&self, name: &str) -> Result<T, Error>
+    where
+        T: FromStr,
+        T::Err: fmt::Display,
+    {
+        let val = self.get(name).err_msg(pos!(), msg!("No parameter '{}'", name))?;
+        val.parse()
+            .map_err(|e| err!("Unable to parse parameter '{}' = '{}': {}", name, val, e))
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.params.iter().map(|(name, val)| (name.as_str(), val.as_str()))
+    }
+
+    pub fn len(&self) -> usize {
+        self.params.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.params.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::path;
+    use crate::path::Pathify;
+
+    path!(Users = "users");
+    type UserId = Pathify<u64>;
+    path!(Posts = "posts");
+    path!(Root -> Users -> UserId -> Posts);
+
+    #[test]
+    fn test_parse() {
+        let pattern = Pattern::parse("users/{id}/posts").unwrap();
+        assert_eq!(pattern.to_string(), "@root/users/{id}/posts");
+        assert_eq!(pattern.params().collect::<Vec<_>>(), vec!["id"]);
+        assert_eq!(Pattern::parse("/users/{id}/posts").unwrap(), pattern);
+        assert!(Pattern::parse("{a}/{a}").is_err());
+
+        // Literals are escaped like pathes, so they are parsed back as is
+        let path = Path::from_parts(["@root", "a/b", "c\\", "d\\\\e", "f\\g"]);
+        let pattern = Pattern::from_path(&path);
+        assert_eq!(pattern.to_string(), path.to_string());
+        assert_eq!(Pattern::parse(&pattern.to_string()).unwrap(), pattern);
+        assert!(pattern.matches(&path).is_some());
+    }
+
+    #[test]
+    fn test_matches() {
+        let pattern = Pattern::parse("users/{id}/posts").unwrap();
+        let caps = pattern.matches(&Path::parse("/users/42/posts")).unwrap();
+        assert_eq!(caps.get("id"), Some("42"));
+        assert_eq!(caps.parse::<u64>("id").unwrap(), 42);
+        assert!(caps.parse::<u64>("name").is_err());
+
+        assert!(pattern.matches(&Path::parse("/users/42")).is_none());
+        assert!(pattern.matches(&Path::parse("/users/42/posts/1")).is_none());
+        assert!(pattern.matches(&Path::parse("/groups/42/posts")).is_none());
+        let caps = pattern.matches(&Path::parse("/users/x/posts")).unwrap();
+        assert!(caps.parse::<u64>("id").is_err());
+    }
+
+    #[test]
+    fn test_typed() {
+        let pattern = path!(pattern [] / Users / UserId / Posts);
+        assert_eq!(pattern, Pattern::parse("users/{}/posts").unwrap());
+
+        let path = path!([] / Users / UserId[42] / Posts).path();
+        let (posts, caps) = pattern.matches_typed::<Posts>(&path).unwrap();
+        assert_eq!(posts.path(), path);
+        assert_eq!(caps.nth(0), Some("42"));
+
+        let pattern = path!(pattern [] / Users / UserId);
+        let (id, _) = pattern
+            .matches_typed::<UserId>(&Path::parse("/users/42"))
+            .unwrap();
+        assert_eq!(id.value, 42);
+        assert!(pattern
+            .matches_typed::<UserId>(&Path::parse("/users/x"))
+            .is_none());
+    }
+}
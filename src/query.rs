@@ -0,0 +1,76 @@
+//! Queries over many nodes at once
+
+use crate::*;
+
+use super::{LmdbResultExt, ResultExt};
+use path::glob::Glob;
+
+impl Storage {
+    /// Returns all existing pathes matching the glob pattern, sorted. See `path::glob` for the syntax.
+    ///
+    /// Only the subtree of the literal prefix of pattern is visited,
+    /// and branches are not visited when nothing inside of them can match.
+    pub fn glob(&self, pattern: &str) -> Result<Vec<Path>, Error> {
+        let glob = Glob::parse(pattern)?;
+        let ro = self.env.begin_ro_txn().lmdb(None, pos!())?;
+        glob_impl(&ro, self.db, &glob).trace_pos(pos!(quiet pattern))
+    }
+
+    /// Same as `glob`, but also loads values of all matching nodes in the same transaction.
+    ///
+    /// Stored values do not know their type, so pattern may match nodes of other families.
+    /// Nodes which cannot be loaded as `T` are skipped with a debug message.
+    pub fn glob_typed<T: Schema>(&self, pattern: &str) -> Result<Vec<(Path, T)>, Error> {
+        let glob = Glob::parse(pattern)?;
+        let ro = self.env.begin_ro_txn().lmdb(None, pos!())?;
+        let mut res = Vec::new();
+        for path in glob_impl(&ro, self.db, &glob).trace_pos(pos!(quiet pattern))? {
+            match RoTransactionExt::get(&ro, self.db, &path, &self.metrics) {
+                Ok(Some(val)) => res.push((path, val)),
+                Ok(None) => {}
+                Err(e @ Error::Decode { .. }) | Err(e @ Error::VersionChain { .. }) => {
+                    slog::debug!(self.logger(), "glob_typed skips {}", path; "error" => %e);
+                }
+                Err(e) => return Err(e).trace_pos(pos!()),
+            }
+        }
+        Ok(res)
+    }
+}
+
+pub(crate) fn glob_impl<Txn: lmdb::Transaction>(
+    txn: &Txn,
+    db: lmdb::Database,
+    glob: &Glob,
+) -> Result<Vec<Path>, Error> {
+    // Walk the prefix without reading it, it contains no wildcards
+    let prefix = glob.prefix();
+    let mut state = glob.start();
+    for part in prefix.components() {
+        state = glob.advance(&state, part);
+    }
+
+    let mut res = Vec::new();
+    let mut queue = vec![(prefix, state)];
+    while let Some((current, state)) = queue.pop() {
+        let info: Option<DataWrapperV1> =
+            RoTransactionExt::info(txn, db, &current).trace_pos(pos!(current))?;
+        let info = match info {
+            None => continue,
+            Some(info) => info,
+        };
+        if glob.is_match(&state) {
+            res.push(current.clone());
+        }
+        for child in info.children {
+            let next = glob.advance(&state, &child);
+            if !next.is_empty() {
+                let mut path = current.clone();
+                path.push(&child);
+                queue.push((path, next));
+            }
+        }
+    }
+    res.sort();
+    Ok(res)
+}
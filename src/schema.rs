@@ -1,6 +1,9 @@
 use std::fmt::Debug;
 use my_error::*;
 
+pub mod chain;
+pub mod testing;
+
 pub trait FirstVersionMarker {}
 
 /// Part of `Schema` trait. Default implementation provided for `FirstVersionMarker` types
@@ -75,6 +78,17 @@ impl<T> SchemaVersion for T where T: FirstVersionMarker {
 /// Reexport static_Assertions for def_schema macro
 pub use static_assertions;
 
+/// Reexport crates used by the code generated by `#[derive(Schema)]`
+pub use my_error;
+pub use serde;
+
+/// Derive macro for the `Schema`. Same as `def_schema!`, but also supports generic types.
+///
+/// `#[schema(version = 3, prev = FooV2, serde)]` is the same as `def_schema!(FooV3 = 3; serde)`
+/// with `SchemaUpgrade` implemented through `From<FooV2>`.
+/// See `lmtreedb_derive` crate for all arguments.
+pub use lmtreedb_derive::Schema;
+
 /// This macro helps implementing schema.
 /// For example `def_schema!(MyData = 1; serde)` means:
 /// - `MyData` implements `SchemaVersion` with version = 1
@@ -82,6 +96,12 @@ pub use static_assertions;
 /// - Also it is marked as `serde`, so `SchemaSerdeMarker` is added
 ///
 /// You can mark version as last by writing it in square brackets: `def_schema!(LastVer = [5])`
+///
+/// Direct upgrades from older versions are listed in `shortcut`:
+/// `def_schema!(LastVer = [5]; serde, shortcut(FirstVer, SecondVer))`.
+/// Each of them requires `SchemaUpgradeFrom` implementation.
+///
+/// Upgrade from the previous version can be marked as `lossless` or `lossy`, see `Schema::lossiness`
 #[macro_export]
 macro_rules! def_schema {
     // Deny zero. This check can be bypassed btw
@@ -114,13 +134,53 @@ macro_rules! def_schema {
         $crate::def_schema!(@expand [$t] check_prev, check_next, $($args)*);
     };
     // Finish implementation
-    (@expand [$t:ty] $($args:tt),* $(,)?) => {
-        // Implement Schema for type
-        impl $crate::schema::Schema for $t {}
+    (@expand [$t:ty] $($args:tt)*) => {
+        $crate::def_schema!(@schema [$t] [] [] $($args)*);
+    };
+    // Collect all shortcuts and lossiness, because they are required to implement Schema itself
+    (@schema [$t:ty] [$($sc:ty,)*] [$($loss:ident)?] shortcut($($new:ty),* $(,)?) $(, $($rest:tt)*)?) => {
+        $crate::def_schema!(@schema [$t] [$($sc,)* $($new,)*] [$($loss)?] $($($rest)*)?);
+    };
+    (@schema [$t:ty] [$($sc:ty,)*] [] lossless $(, $($rest:tt)*)?) => {
+        $crate::def_schema!(@schema [$t] [$($sc,)*] [Lossless] $($($rest)*)?);
+    };
+    (@schema [$t:ty] [$($sc:ty,)*] [] lossy $(, $($rest:tt)*)?) => {
+        $crate::def_schema!(@schema [$t] [$($sc,)*] [Lossy] $($($rest)*)?);
+    };
+    // Everything else is implemented separately
+    (@schema [$t:ty] [$($sc:ty,)*] [$($loss:ident)?] $arg:tt $(, $($rest:tt)*)?) => {
+        $crate::def_schema!(@impl [$t] $arg);
+        $crate::def_schema!(@schema [$t] [$($sc,)*] [$($loss)?] $($($rest)*)?);
+    };
+    // Implement Schema for type
+    (@schema [$t:ty] [$($sc:ty,)*] [$($loss:ident)?]) => {
+        impl $crate::schema::Schema for $t {
+            $crate::def_schema!(@shortcut $($sc,)*);
+            $(
+                fn lossiness() -> $crate::schema::Lossiness {
+                    $crate::schema::Lossiness::$loss
+                }
+            )?
+        }
         $(
-            $crate::def_schema!(@impl [$t] $args);
+            // Shortcuts are only for upgrades
+            $crate::schema::static_assertions::const_assert!(
+                <$sc as $crate::schema::SchemaVersion>::VERSION
+                    < <$t as $crate::schema::SchemaVersion>::VERSION
+            );
         )*
     };
+    (@shortcut) => {};
+    (@shortcut $($sc:ty,)+) => {
+        fn shortcut(version: u64) -> Option<$crate::schema::Shortcut<Self>> {
+            $(
+                if version == <$sc as $crate::schema::SchemaVersion>::VERSION {
+                    return Some($crate::schema::shortcut::<$sc, Self>);
+                }
+            )+
+            None
+        }
+    };
     (@impl [$t:ty] serde) => {
         impl $crate::schema::SchemaSerdeMarker for $t {}
     };
@@ -156,6 +216,49 @@ pub trait Schema: Debug + SchemaUpgrade + SchemaDowngrade + SchemaSerde + Schema
     fn version() -> u64 {
         Self::VERSION
     }
+
+    /// Returns direct conversion from the given older version if it exists.
+    ///
+    /// When `None` is returned, data is upgraded one version at a time.
+    /// Implemented by `def_schema!` for versions listed in `shortcut(...)`
+    fn shortcut(_version: u64) -> Option<Shortcut<Self>> {
+        None
+    }
+
+    /// Tells whether upgrade from PrevVersion followed by downgrade back keeps all the data.
+    ///
+    /// Implemented by `def_schema!` if `lossless` or `lossy` is specified.
+    fn lossiness() -> Lossiness {
+        Lossiness::Unknown
+    }
+}
+
+/// See `Schema::lossiness`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Lossiness {
+    /// Nothing is declared
+    Unknown,
+    /// Upgrade followed by downgrade returns exactly the same value
+    Lossless,
+    /// Some data may be lost
+    Lossy,
+}
+
+/// Converts raw data of some older version directly to the `T`. See `Schema::shortcut`
+pub type Shortcut<T> = fn(rmpv::Value) -> Result<T, Error>;
+
+/// Direct upgrade from `Old`, skipping all intermediate versions.
+///
+/// It is used only if `Old` is listed in `shortcut(...)` of `def_schema!`
+pub trait SchemaUpgradeFrom<Old: Schema>: Schema {
+    fn upgrade_from(val: Old) -> Result<Self, Error>;
+}
+
+/// Deserializes raw data as `Old` and upgrades it directly to the `New`
+pub fn shortcut<Old: Schema, New: SchemaUpgradeFrom<Old>>(val: rmpv::Value) -> Result<New, Error> {
+    let old = Old::load(val).epos(pos!(quiet std::intrinsics::type_name::<Old>()))?;
+    let res = New::upgrade_from(old).epos(pos!(quiet std::intrinsics::type_name::<New>()))?;
+    Ok(res)
 }
 
 /// Use this type for non-existing version in `Schema::PrevVersion` and `Schema::NextVersion`
@@ -201,6 +304,33 @@ pub trait SingleVersionMarker
 impl<T: SingleVersionMarker> FirstVersionMarker for T {}
 impl<T: SingleVersionMarker> LastVersionMarker for T {}
 
+/// Saves value together with its version as array of two records: `[version, data]`.
+///
+/// Such value can be loaded later as any other version of `T` using `load_versioned`.
+pub fn save_versioned<T: Schema>(val: T) -> Result<rmpv::Value, Error> {
+    let data = val.save().epos(pos!())?;
+    Ok(rmpv::Value::Array(vec![rmpv::Value::from(T::version()), data]))
+}
+
+/// Loads value saved by `save_versioned`. Does all required upgrades or downgrades.
+pub fn load_versioned<T: Schema>(val: rmpv::Value) -> Result<T, Error> {
+    let mut arr = match val {
+        rmpv::Value::Array(arr) => arr,
+        other => return Err(err!("Invalid format: {}", other)),
+    };
+    if arr.len() != 2 {
+        return Err(err!("Invalid format"));
+    }
+
+    // Split internal data
+    let data = arr.pop().err(pos!())?;
+    let version = arr[0].as_u64().err(pos!())?;
+
+    // And deserialize saved version to the required
+    let res = crate::load(version, data).epos(pos!(version))?;
+    Ok(res)
+}
+
 macro_rules! simple_type {
     { $($t:ty),* } => {
         $(
@@ -215,6 +345,6 @@ simple_type! {
     u32, i32,
     u64, i64,
     f32, f64,
-    String, Vec<u8>,
+    String,
     bool, ()
 }
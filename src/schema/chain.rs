@@ -0,0 +1,315 @@
+//! Introspection of schema families.
+//!
+//! The chain of versions exists only through `SchemaUpgrade::PrevVersion` and
+//! `SchemaDowngrade::NextVersion`, so it is collected by following these types.
+//!
+//! ```ignore
+//! let chain = describe::<UserV3>();
+//! println!("{}", chain);
+//! std::fs::write("users.dot", chain.to_dot())?;
+//! ```
+
+use std::collections::BTreeMap;
+use std::fmt;
+
+use super::{Lossiness, Schema};
+
+/// Single version of schema family
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct VersionInfo {
+    pub version: u64,
+    pub type_name: &'static str,
+    /// Version of `PrevVersion`, zero if there is no previous version
+    pub prev: u64,
+    /// Version of `NextVersion`, zero if there is no next version
+    pub next: u64,
+    /// Lossiness of upgrade from the previous version
+    pub lossiness: Lossiness,
+    /// Older versions which have direct upgrade to this one, see `Schema::shortcut`
+    pub shortcuts: Vec<u64>,
+}
+
+/// Conversion between two neighbour versions
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Step {
+    /// Older version
+    pub from: u64,
+    /// Newer version, always `from + 1`
+    pub to: u64,
+    /// `from` can be upgraded to `to`
+    pub upgrade: bool,
+    /// `to` can be downgraded to `from`
+    pub downgrade: bool,
+    pub lossiness: Lossiness,
+}
+
+/// All known versions of schema family, sorted by version
+#[derive(Clone, Debug, Default)]
+pub struct Chain {
+    pub versions: Vec<VersionInfo>,
+}
+
+/// Collects all versions reachable from `T` through `PrevVersion` and `NextVersion`.
+///
+/// Newer versions which cannot be downgraded are not linked from older ones, so they are missing
+/// from the chain of an older `T`. `Constraint` and `Index` take this chain as the whole family.
+pub fn describe<T: Schema>() -> Chain {
+    let mut found = BTreeMap::new();
+    collect_prev::<T>(&mut found);
+    collect_next::<T>(&mut found);
+    Chain {
+        versions: found.values().cloned().collect(),
+    }
+}
+
+fn info<T: Schema>() -> VersionInfo {
+    let version = T::version();
+    VersionInfo {
+        version,
+        type_name: std::intrinsics::type_name::<T>(),
+        prev: T::PrevVersion::version(),
+        next: T::NextVersion::version(),
+        lossiness: T::lossiness(),
+        shortcuts: (1..version).filter(|&v| T::shortcut(v).is_some()).collect(),
+    }
+}
+
+/// Adds `T` and all older versions
+fn collect_prev<T: Schema>(found: &mut BTreeMap<u64, VersionInfo>) {
+    let version = T::version();
+    if version == 0 || found.contains_key(&version) {
+        return;
+    }
+    found.insert(version, info::<T>());
+    // Invalid chains are listed as is, but not followed
+    if T::PrevVersion::version() < version {
+        collect_prev::<T::PrevVersion>(found);
+    }
+}
+
+/// Adds `T` and all newer versions
+fn collect_next<T: Schema>(found: &mut BTreeMap<u64, VersionInfo>) {
+    let version = T::version();
+    if version == 0 {
+        return;
+    }
+    found.entry(version).or_insert_with(info::<T>);
+    if T::NextVersion::version() > version {
+        collect_next::<T::NextVersion>(found);
+    }
+}
+
+impl Chain {
+    pub fn first(&self) -> Option<&VersionInfo> {
+        self.versions.first()
+    }
+
+    pub fn last(&self) -> Option<&VersionInfo> {
+        self.versions.last()
+    }
+
+    pub fn get(&self, version: u64) -> Option<&VersionInfo> {
+        self.versions.iter().find(|x| x.version == version)
+    }
+
+    /// Steps between all neighbour versions found in the chain
+    pub fn steps(&self) -> Vec<Step> {
+        self.versions
+            .windows(2)
+            .filter(|w| w[0].version + 1 == w[1].version)
+            .map(|w| Step {
+                from: w[0].version,
+                to: w[1].version,
+                upgrade: w[1].prev == w[0].version,
+                downgrade: w[0].next == w[1].version,
+                lossiness: w[1].lossiness,
+            })
+            .collect()
+    }
+
+    /// Renders chain in the Graphviz `dot` format.
+    ///
+    /// Upgrades are solid edges, downgrades are dashed, shortcuts are dotted. Lossy steps are red.
+    pub fn to_dot(&self) -> String {
+        let mut res = String::from("digraph schema {\n    rankdir=LR;\n");
+        for v in &self.versions {
+            res += &format!(
+                "    v{} [shape=box, label=\"v{}\\n{}\"];\n",
+                v.version,
+                v.version,
+                escape(v.type_name)
+            );
+        }
+        for step in self.steps() {
+            let color = match step.lossiness {
+                Lossiness::Lossy => ", color=red",
+                Lossiness::Lossless | Lossiness::Unknown => "",
+            };
+            if step.upgrade {
+                res += &format!(
+                    "    v{} -> v{} [label=\"{}\"{}];\n",
+                    step.from,
+                    step.to,
+                    lossiness_label(step.lossiness),
+                    color
+                );
+            }
+            if step.downgrade {
+                res += &format!("    v{} -> v{} [style=dashed{}];\n", step.to, step.from, color);
+            }
+        }
+        for v in &self.versions {
+            for sc in &v.shortcuts {
+                res += &format!("    v{} -> v{} [style=dotted];\n", sc, v.version);
+            }
+        }
+        res += "}\n";
+        res
+    }
+}
+
+/// Renders chain as plain text:
+///
+/// ```text
+/// v1 crate::UserV1
+///    |  upgrade, downgrade
+/// v2 crate::UserV2
+///    |  upgrade only (lossy)
+/// v3 crate::UserV3
+///       shortcut from v1
+/// ```
+impl fmt::Display for Chain {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let steps = self.steps();
+        for v in &self.versions {
+            if let Some(step) = steps.iter().find(|x| x.to == v.version) {
+                let dirs = match (step.upgrade, step.downgrade) {
+                    (true, true) => "upgrade, downgrade",
+                    (true, false) => "upgrade only",
+                    (false, true) => "downgrade only",
+                    (false, false) => "no conversion",
+                };
+                write!(f, "   |  {}", dirs)?;
+                match step.lossiness {
+                    Lossiness::Unknown => writeln!(f)?,
+                    other => writeln!(f, " ({})", lossiness_label(other))?,
+                }
+            }
+            writeln!(f, "v{} {}", v.version, v.type_name)?;
+            for sc in &v.shortcuts {
+                writeln!(f, "      shortcut from v{}", sc)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn lossiness_label(lossiness: Lossiness) -> &'static str {
+    match lossiness {
+        Lossiness::Unknown => "",
+        Lossiness::Lossless => "lossless",
+        Lossiness::Lossy => "lossy",
+    }
+}
+
+fn escape(s: &str) -> String {
+    s.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::*;
+    use my_error::Error;
+
+    #[derive(Debug, serde::Serialize, serde::Deserialize)]
+    struct ChainV1(u32);
+    def_schema!(ChainV1 = 1; serde);
+
+    #[derive(Debug, serde::Serialize, serde::Deserialize)]
+    struct ChainV2(u32);
+    // Cannot be downgraded
+    def_schema!(ChainV2 = [2]; serde, lossless);
+
+    #[derive(Debug, serde::Serialize, serde::Deserialize)]
+    struct ChainV3(u64);
+    def_schema!(ChainV3 = [3]; serde, lossy, shortcut(ChainV1));
+
+    impl SchemaDowngrade for ChainV1 {
+        type NextVersion = ChainV2;
+        fn downgrade(val: ChainV2) -> Result<Self, Error> {
+            Ok(ChainV1(val.0))
+        }
+    }
+
+    impl SchemaUpgrade for ChainV2 {
+        type PrevVersion = ChainV1;
+        fn upgrade(val: ChainV1) -> Result<Self, Error> {
+            Ok(ChainV2(val.0))
+        }
+    }
+
+    impl SchemaUpgrade for ChainV3 {
+        type PrevVersion = ChainV2;
+        fn upgrade(val: ChainV2) -> Result<Self, Error> {
+            Ok(ChainV3(val.0 as u64))
+        }
+    }
+
+    impl SchemaUpgradeFrom<ChainV1> for ChainV3 {
+        fn upgrade_from(val: ChainV1) -> Result<Self, Error> {
+            Ok(ChainV3(val.0 as u64))
+        }
+    }
+
+    #[test]
+    fn test_describe() {
+        // Only versions linked through PrevVersion and NextVersion are found,
+        // ChainV3 cannot be downgraded, so it is unknown to ChainV1
+        let chain = describe::<ChainV1>();
+        let versions: Vec<u64> = chain.versions.iter().map(|x| x.version).collect();
+        assert_eq!(versions, vec![1, 2]);
+
+        let chain = describe::<ChainV3>();
+        let versions: Vec<u64> = chain.versions.iter().map(|x| x.version).collect();
+        assert_eq!(versions, vec![1, 2, 3]);
+        assert_eq!(chain.get(3).unwrap().shortcuts, vec![1]);
+
+        let steps = chain.steps();
+        assert_eq!(
+            steps,
+            vec![
+                Step {
+                    from: 1,
+                    to: 2,
+                    upgrade: true,
+                    downgrade: true,
+                    lossiness: Lossiness::Lossless
+                },
+                Step {
+                    from: 2,
+                    to: 3,
+                    upgrade: true,
+                    downgrade: false,
+                    lossiness: Lossiness::Lossy
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_render() {
+        let chain = describe::<ChainV3>();
+        let text = chain.to_string();
+        assert!(text.contains("|  upgrade only (lossy)\n"));
+        assert!(text.contains("shortcut from v1\n"));
+
+        let dot = chain.to_dot();
+        assert!(dot.starts_with("digraph schema {"));
+        assert!(dot.contains("v1 -> v2 [label=\"lossless\"];"));
+        assert!(dot.contains("v2 -> v1 [style=dashed];"));
+        assert!(dot.contains("v2 -> v3 [label=\"lossy\", color=red];"));
+        assert!(!dot.contains("v3 -> v2"));
+        assert!(dot.contains("v1 -> v3 [style=dotted];"));
+    }
+}
@@ -0,0 +1,248 @@
+//! Helpers to test schema chains.
+//!
+//! ```ignore
+//! let mut fixtures = Fixtures::default();
+//! fixtures.add_value(UserV1 { name: "user".to_string() })?;
+//! fixtures.add(2, raw_value_from_production);
+//! verify::<UserV3>(&fixtures).assert_ok();
+//! ```
+
+use std::collections::BTreeMap;
+use std::fmt;
+
+use rmpv::Value;
+
+use crate::*;
+use my_error::Error;
+
+/// Stored values of single schema family, grouped by their versions
+#[derive(Clone, Debug, Default)]
+pub struct Fixtures {
+    values: BTreeMap<u64, Vec<Value>>,
+}
+
+impl Fixtures {
+    /// Adds raw value as it is stored in the database
+    pub fn add(&mut self, version: u64, val: Value) {
+        self.values.entry(version).or_default().push(val);
+    }
+
+    /// Saves value and adds it with version of `T`
+    pub fn add_value<T: Schema>(&mut self, val: T) -> Result<(), Error> {
+        let val = val.save().epos(pos!())?;
+        self.add(T::version(), val);
+        Ok(())
+    }
+
+    /// Returns all values of the specified version
+    pub fn get(&self, version: u64) -> &[Value] {
+        self.values.get(&version).map(|x| &x[..]).unwrap_or(&[])
+    }
+}
+
+/// What was checked by `verify`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Check {
+    /// Saving loaded value and loading it again gives the same result
+    RoundTrip,
+    /// Upgrade followed by downgrade gives the same value. Only for `Lossiness::Lossless` versions.
+    Lossless,
+    /// Value can be loaded as the latest version. Fails if there are no fixtures of the version.
+    Reachable,
+    /// PrevVersion of each version is the previous one, down to the first version
+    Chain,
+}
+
+/// Single failed check
+#[derive(Debug)]
+pub struct Failure {
+    pub check: Check,
+    /// Version being checked
+    pub version: u64,
+    pub error: Error,
+}
+
+/// Result of `verify`
+#[derive(Debug, Default)]
+pub struct Report {
+    pub failures: Vec<Failure>,
+}
+
+impl Report {
+    pub fn is_ok(&self) -> bool {
+        self.failures.is_empty()
+    }
+
+    /// Panics with all failures if any. Useful in tests.
+    pub fn assert_ok(&self) {
+        if !self.is_ok() {
+            panic!("Schema chain verification failed:\n{}", self);
+        }
+    }
+
+    fn fail(&mut self, check: Check, version: u64, error: Error) {
+        self.failures.push(Failure {
+            check,
+            version,
+            error,
+        })
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for failure in &self.failures {
+            writeln!(
+                f,
+                "{:?} check of version {} failed: {}",
+                failure.check, failure.version, failure.error
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Verifies whole chain of versions from the first one up to the `T`, using provided fixtures.
+///
+/// For every version:
+/// - each fixture survives `load` -> `save` -> `load` -> `save` without changes
+/// - each fixture can be loaded as `T`, and there is at least one fixture
+/// - if version is `Lossless`, each fixture of previous version is not changed by upgrade and downgrade back
+/// - previous version is exactly one less than current
+pub fn verify<T: Schema>(fixtures: &Fixtures) -> Report {
+    let mut report = Report::default();
+    verify_version::<T, T>(fixtures, &mut report);
+    report
+}
+
+/// Checks version `T` and all previous. `L` is the latest version.
+fn verify_version<T: Schema, L: Schema>(fixtures: &Fixtures, report: &mut Report) {
+    let version = T::version();
+    if fixtures.get(version).is_empty() {
+        report.fail(Check::Reachable, version, err!("No fixtures for version {}", version));
+    }
+    for raw in fixtures.get(version) {
+        if let Err(e) = round_trip::<T>(raw.clone()) {
+            report.fail(Check::RoundTrip, version, e);
+        }
+        if let Err(e) = load::<L>(version, raw.clone()) {
+            report.fail(Check::Reachable, version, e);
+        }
+    }
+
+    if version <= 1 {
+        return;
+    }
+    if T::PrevVersion::version() + 1 != version {
+        report.fail(
+            Check::Chain,
+            version,
+            err!(
+                "PrevVersion of {} has version {}",
+                std::intrinsics::type_name::<T>(),
+                T::PrevVersion::version()
+            ),
+        );
+        return;
+    }
+
+    if T::lossiness() == Lossiness::Lossless {
+        for raw in fixtures.get(version - 1) {
+            if let Err(e) = lossless::<T>(raw.clone()) {
+                report.fail(Check::Lossless, version, e);
+            }
+        }
+    }
+
+    verify_version::<T::PrevVersion, L>(fixtures, report);
+}
+
+fn round_trip<T: Schema>(raw: Value) -> Result<(), Error> {
+    let first = load::<T>(T::version(), raw).epos(pos!())?.save().epos(pos!())?;
+    let second = load::<T>(T::version(), first.clone())
+        .epos(pos!())?
+        .save()
+        .epos(pos!())?;
+    if first != second {
+        return Err(err!("Value changed: {} -> {}", first, second));
+    }
+    Ok(())
+}
+
+/// Upgrades raw value of `T::PrevVersion` to `T` and downgrades it back
+fn lossless<T: Schema>(raw: Value) -> Result<(), Error> {
+    let prev = T::PrevVersion::version();
+    let expected = load::<T::PrevVersion>(prev, raw.clone())
+        .epos(pos!())?
+        .save()
+        .epos(pos!())?;
+    let upgraded = load::<T>(prev, raw).epos(pos!())?.save().epos(pos!())?;
+    let actual = load::<T::PrevVersion>(T::version(), upgraded)
+        .epos(pos!())?
+        .save()
+        .epos(pos!())?;
+    if expected != actual {
+        return Err(err!("Value changed: {} -> {}", expected, actual));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[derive(Debug, serde::Serialize, serde::Deserialize)]
+    struct ItemV1 {
+        name: String,
+    }
+
+    def_schema!(ItemV1 = 1; serde);
+
+    #[derive(Debug, serde::Serialize, serde::Deserialize)]
+    struct ItemV2 {
+        name: String,
+        count: u32,
+    }
+
+    def_schema!(ItemV2 = [2]; serde, lossless);
+
+    def_fields!(ItemV1 => ItemV2 {
+        add count = 1u32;
+    });
+
+    fn fixtures() -> Fixtures {
+        let mut res = Fixtures::default();
+        res.add_value(ItemV1 {
+            name: "a".to_string(),
+        })
+        .unwrap();
+        res.add_value(ItemV2 {
+            name: "b".to_string(),
+            count: 2,
+        })
+        .unwrap();
+        res
+    }
+
+    #[test]
+    fn test_verify() {
+        verify::<ItemV2>(&fixtures()).assert_ok();
+    }
+
+    #[test]
+    fn test_invalid_fixture() {
+        let mut fixtures = fixtures();
+        fixtures.add(1, Value::from(5));
+        let report = verify::<ItemV2>(&fixtures);
+        let checks: Vec<Check> = report.failures.iter().map(|x| x.check).collect();
+        assert_eq!(checks, vec![Check::Lossless, Check::RoundTrip, Check::Reachable]);
+    }
+
+    #[test]
+    fn test_no_fixtures() {
+        let report = verify::<ItemV2>(&Fixtures::default());
+        let failed: Vec<(Check, u64)> =
+            report.failures.iter().map(|x| (x.check, x.version)).collect();
+        assert_eq!(failed, vec![(Check::Reachable, 2), (Check::Reachable, 1)]);
+    }
+}
@@ -0,0 +1,78 @@
+//! Handles restricted to a subtree, see `Storage::scope`.
+
+use crate::*;
+
+use super::ResultExt;
+
+/// Storage handle that accepts only pathes relative to its root and cannot leave it.
+///
+/// Relative pathes are resolved with `Path::join`, so `..` is allowed while it stays inside.
+/// Absolute pathes and pathes going above the root are rejected with `Error::OutOfScope`.
+#[derive(Clone, Debug)]
+pub struct Scope<'a> {
+    storage: &'a Storage,
+    root: Path,
+}
+
+impl Storage {
+    /// Creates scope of the subtree at `root`. It is not required to exist.
+    ///
+    /// ```ignore
+    /// let scope = db.scope(&Path::parse("/plugins/foo"));
+    /// scope.put(&Path::parse("settings"), settings)?;
+    /// ```
+    pub fn scope(&self, root: &Path) -> Scope<'_> {
+        Scope {
+            storage: self,
+            root: root.clone(),
+        }
+    }
+}
+
+impl<'a> Scope<'a> {
+    /// Absolute path of the scope root
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Converts relative path to the absolute one, checking that it stays inside of the scope
+    pub fn resolve(&self, path: &Path) -> Result<Path, Error> {
+        let full = self.root.join(path);
+        if path.is_absolute() || !full.starts_with(&self.root) {
+            return Err(Error::OutOfScope {
+                path: path.clone(),
+                trace: err!("'{}' is outside of '{}'", path, self.root),
+            });
+        }
+        Ok(full)
+    }
+
+    /// Nested scope, which root is relative to this one
+    pub fn scope(&self, path: &Path) -> Result<Scope<'a>, Error> {
+        Ok(Scope {
+            storage: self.storage,
+            root: self.resolve(path).trace_pos(pos!())?,
+        })
+    }
+
+    pub fn children<T: DataWrapper>(&self, path: &Path) -> Result<Option<T>, Error> {
+        let full = self.resolve(path).trace_pos(pos!())?;
+        self.storage.children(&full).trace_pos(pos!())
+    }
+
+    pub fn get<T: Schema>(&self, path: &Path) -> Result<Option<T>, Error> {
+        let full = self.resolve(path).trace_pos(pos!())?;
+        self.storage.get(&full).trace_pos(pos!())
+    }
+
+    /// Puts the value. Parent must exist, the same as for `Storage::put`
+    pub fn put<T: Schema>(&self, path: &Path, val: T) -> Result<(), Error> {
+        let full = self.resolve(path).trace_pos(pos!())?;
+        self.storage.put(&full, val).trace_pos(pos!())
+    }
+
+    pub fn del(&self, path: &Path) -> Result<(), Error> {
+        let full = self.resolve(path).trace_pos(pos!())?;
+        self.storage.del(&full).trace_pos(pos!())
+    }
+}
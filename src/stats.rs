@@ -0,0 +1,117 @@
+//! Statistics of the LMDB environment and of the stored tree. See `Storage::stats` and `Storage::du`.
+
+use std::collections::BTreeMap;
+
+use lmdb_sys as ffi;
+
+use crate::*;
+
+use super::{LmdbResultExt, ResultExt};
+
+/// Information about LMDB environment, returned by `Storage::stats`
+#[derive(Clone, Debug)]
+pub struct Stats {
+    /// Size of the memory map, in bytes
+    pub map_size: usize,
+    pub page_size: u32,
+    /// Number of pages used from the memory map
+    pub used_pages: usize,
+    pub last_txn_id: usize,
+    pub max_readers: u32,
+    /// Number of reader slots used, including stale ones
+    pub readers: u32,
+    pub databases: Vec<DbStats>,
+}
+
+impl Stats {
+    /// Bytes used from the memory map
+    pub fn used_bytes(&self) -> usize {
+        self.used_pages * self.page_size as usize
+    }
+}
+
+/// B-tree statistics of single LMDB database
+#[derive(Clone, Debug)]
+pub struct DbStats {
+    pub name: &'static str,
+    pub depth: u32,
+    pub branch_pages: usize,
+    pub leaf_pages: usize,
+    pub overflow_pages: usize,
+    pub entries: usize,
+}
+
+/// Disk usage of subtree, returned by `Storage::du`
+#[derive(Clone, Debug, Default)]
+pub struct Usage {
+    pub nodes: usize,
+    /// Size of stored nodes, including service info like list of children
+    pub bytes: usize,
+    /// Number of nodes for each stored version
+    pub versions: BTreeMap<u64, usize>,
+}
+
+impl Storage {
+    /// Returns information about LMDB environment and all its databases
+    pub fn stats(&self) -> Result<Stats, Error> {
+        let mut info: ffi::MDB_envinfo = unsafe { std::mem::zeroed() };
+        lmdb_call(unsafe { ffi::mdb_env_info(self.env.env(), &mut info) }).lmdb(None, pos!())?;
+        let page_size = self.env.stat().lmdb(None, pos!())?.page_size();
+
+        let ro = self.env.begin_ro_txn().lmdb(None, pos!())?;
+        let mut databases = vec![db_stats(&ro, "tree", self.db).trace_pos(pos!())?];
+        if let Some(db) = self.index_db {
+            databases.push(db_stats(&ro, "index", db).trace_pos(pos!())?);
+        }
+        Ok(Stats {
+            map_size: info.me_mapsize,
+            page_size,
+            used_pages: info.me_last_pgno + 1,
+            last_txn_id: info.me_last_txnid,
+            max_readers: info.me_maxreaders,
+            readers: info.me_numreaders,
+            databases,
+        })
+    }
+
+    /// Computes disk usage of the subtree, including `path` itself. Everything is read in single transaction.
+    pub fn du(&self, path: &Path) -> Result<Usage, Error> {
+        let ro = self.env.begin_ro_txn().lmdb(None, pos!())?;
+        let mut res = Usage::default();
+        for current in RoTransactionExt::subtree(&ro, self.db, path).trace_pos(pos!())? {
+            let (info, bytes): (DataWrapperV1, usize) =
+                RoTransactionExt::info_sized(&ro, self.db, &current)
+                    .trace_pos(pos!())?
+                    .err(pos!(current))?;
+            res.nodes += 1;
+            res.bytes += bytes;
+            *res.versions.entry(info.version).or_insert(0) += 1;
+        }
+        Ok(res)
+    }
+}
+
+fn db_stats<Txn: lmdb::Transaction>(
+    txn: &Txn,
+    name: &'static str,
+    db: lmdb::Database,
+) -> Result<DbStats, Error> {
+    let mut stat: ffi::MDB_stat = unsafe { std::mem::zeroed() };
+    lmdb_call(unsafe { ffi::mdb_stat(txn.txn(), db.dbi(), &mut stat) }).lmdb(None, pos!())?;
+    Ok(DbStats {
+        name,
+        depth: stat.ms_depth,
+        branch_pages: stat.ms_branch_pages,
+        leaf_pages: stat.ms_leaf_pages,
+        overflow_pages: stat.ms_overflow_pages,
+        entries: stat.ms_entries,
+    })
+}
+
+/// Converts return code of LMDB function to Result
+fn lmdb_call(code: i32) -> Result<(), lmdb::Error> {
+    match code {
+        0 => Ok(()),
+        code => Err(lmdb::Error::from_err_code(code)),
+    }
+}
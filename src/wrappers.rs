@@ -2,45 +2,28 @@ use std::collections::HashSet;
 use std::iter::FromIterator;
 
 use crate::*;
+use my_error::Error;
 
-use super::load;
 use super::Schema;
 
 /// Stores only DataWrapper data and version
 ///
 /// Internally serializes to two records: DataWrapper's data and DataWrapper's version.
 /// Can be desserialized to any DataWrapper version, using upgrades and downgrades if required
-#[derive(Debug)]
+#[derive(Debug, Schema)]
+#[schema(version = 1, last)]
 pub struct VersionWrapper<T: DataWrapper> {
     pub data: T,
 }
 
-// We cannot use def_schema here because of generic arguments
-impl<T: DataWrapper> SingleVersionMarker for VersionWrapper<T> {}
-impl<T: DataWrapper> Schema for VersionWrapper<T> {}
-
 impl<T: DataWrapper> SchemaSerde for VersionWrapper<T> {
     fn load(val: rmpv::Value) -> Result<Self, Error> {
-        // Load internal array of two records
-        let arr = val.as_array().err(pos!(val))?;
-        if arr.len() != 2 {
-            return Err(err!("Invalid format"));
-        }
-
-        // Split internal data
-        let version = arr[0].as_u64().err(pos!())?;
-        let data = arr[1].clone();
-
-        // And deserialize saved version (`version`) to the required (`Self::T`)
-        let data = load(version, data).epos(pos!())?;
+        let data = load_versioned(val).epos(pos!())?;
         Ok(Self { data })
     }
 
     fn save(self) -> Result<rmpv::Value, Error> {
-        let mut arr = Vec::with_capacity(2);
-        arr.push(rmpv::Value::from(T::version()));
-        arr.push(self.data.save()?);
-        Ok(rmpv::Value::Array(arr))
+        save_versioned(self.data)
     }
 }
 